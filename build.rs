@@ -0,0 +1,30 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Exposes the git commit and enabled cargo features to the build info
+/// served at `/admin/build`. `GIT_SHA` overrides the commit, for builds
+/// outside a checkout.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    let git_sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(|| {
+            let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_SHA={}", git_sha);
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| Some(name.strip_prefix("CARGO_FEATURE_")?.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=CARGO_FEATURES={}", features.join(","));
+}
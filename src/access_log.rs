@@ -0,0 +1,433 @@
+use std::io::Write;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use axum::{
+    body::{Body, Bytes},
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, Version},
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use ipnet::IpNet;
+use serde_json::{Map, Value};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::fmt::{writer::BoxMakeWriter, MakeWriter};
+
+use crate::auth::Principal;
+use crate::config::{AccessLogConfig, AccessLogFormat};
+use crate::logging::{json::timestamp, sink_writer, utc_datetime};
+use crate::rate_limit::request_client_ip;
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::secret::{is_sensitive_field, mask_field};
+
+const REDACTED: &str = "[REDACTED]";
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Adapts a sink to the `Write` the background writer thread expects.
+struct SinkWriter(BoxMakeWriter);
+
+impl Write for SinkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.make_writer().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.make_writer().flush()
+    }
+}
+
+/// Writes one line per request to the access log sinks, once the response
+/// body has been sent. Lines are handed to a background thread per sink,
+/// so a slow disk never blocks the runtime; dropping the log flushes them.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    writers: Vec<NonBlocking>,
+    _guards: Vec<WorkerGuard>,
+    capture_bodies: bool,
+    max_body_bytes: usize,
+    redact_fields: Vec<String>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl AccessLog {
+    /// Opens the configured sinks. Client IPs are taken from
+    /// `X-Forwarded-For` only behind `config.trusted_proxies`.
+    pub fn new(config: &AccessLogConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let sinks: Vec<BoxMakeWriter> = if config.enabled {
+            config.sinks.iter().map(sink_writer).collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+        let (writers, guards) = sinks
+            .into_iter()
+            .map(|sink| tracing_appender::non_blocking(SinkWriter(sink)))
+            .unzip();
+        Ok(AccessLog {
+            format: config.format,
+            writers,
+            _guards: guards,
+            capture_bodies: config.capture_bodies,
+            max_body_bytes: config.max_body_bytes,
+            redact_fields: config.redact_fields.iter().map(|field| field.to_ascii_lowercase()).collect(),
+            trusted_proxies: config.trusted_proxies.clone(),
+        })
+    }
+
+    pub fn disabled() -> Self {
+        AccessLog {
+            format: AccessLogFormat::default(),
+            writers: Vec::new(),
+            _guards: Vec::new(),
+            capture_bodies: false,
+            max_body_bytes: 0,
+            redact_fields: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    fn is_redacted(&self, field: &str) -> bool {
+        self.redact_fields.iter().any(|redacted| redacted.eq_ignore_ascii_case(field))
+    }
+
+    /// The masked form of a body or query field's value, if it needs one.
+    fn mask(&self, field: &str, value: &str) -> Option<String> {
+        if self.is_redacted(field) {
+            Some(REDACTED.to_string())
+        } else {
+            mask_field(field, value)
+        }
+    }
+
+    fn redact_query(&self, uri: &str) -> String {
+        let Some((path, query)) = uri.split_once('?') else {
+            return uri.to_string();
+        };
+        let pairs: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => match self.mask(name, value) {
+                    Some(masked) => format!("{}={}", name, masked),
+                    None => pair.to_string(),
+                },
+                None => pair.to_string(),
+            })
+            .collect();
+        format!("{}?{}", path, pairs.join("&"))
+    }
+
+    fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    if self.is_redacted(key) || is_sensitive_field(key) {
+                        let raw = value.as_str().map(str::to_owned).unwrap_or_else(|| value.to_string());
+                        *value = Value::from(self.mask(key, &raw).unwrap_or_default());
+                    } else {
+                        self.redact_json(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_json(value)),
+            _ => {}
+        }
+    }
+
+    /// A captured body as it should appear in the log. JSON is redacted
+    /// field by field; JSON cut off at the cap can't be, so it's dropped.
+    fn render_body(&self, body: &BodyRecorder) -> Option<String> {
+        let kind = body.capture?;
+        let captured = body.captured.lock().unwrap();
+        let truncated = body.truncated.load(Ordering::Relaxed);
+        match kind {
+            CaptureKind::Json if truncated => Some("[TRUNCATED]".to_string()),
+            CaptureKind::Json if captured.is_empty() => None,
+            CaptureKind::Json => match serde_json::from_slice::<Value>(&captured) {
+                Ok(mut value) => {
+                    self.redact_json(&mut value);
+                    Some(value.to_string())
+                }
+                Err(_) => Some(REDACTED.to_string()),
+            },
+            CaptureKind::Text => {
+                let mut text = String::from_utf8_lossy(&captured).into_owned();
+                if truncated {
+                    text.push_str("...");
+                }
+                Some(text)
+            }
+        }
+    }
+
+    fn capture_kind(&self, headers: &HeaderMap) -> Option<CaptureKind> {
+        if !self.capture_bodies {
+            return None;
+        }
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?.to_ascii_lowercase();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime == "application/json" || mime.ends_with("+json") {
+            Some(CaptureKind::Json)
+        } else if mime.starts_with("text/") {
+            Some(CaptureKind::Text)
+        } else {
+            None
+        }
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = match self.format {
+            AccessLogFormat::Combined => self.combined(entry),
+            AccessLogFormat::Json => self.json(entry),
+        };
+        line.push('\n');
+        for writer in &self.writers {
+            // Queued, never blocking; a full queue drops the line rather
+            // than stall the request
+            let _ = writer.make_writer().write_all(line.as_bytes());
+        }
+    }
+
+    fn field(&self, name: &str, value: Option<String>) -> Option<String> {
+        match value {
+            Some(_) if self.is_redacted(name) => Some(REDACTED.to_string()),
+            value => value,
+        }
+    }
+
+    fn combined(&self, entry: &Entry) -> String {
+        let quoted = |value: Option<String>| value.map(|value| value.replace('"', "\\\"")).unwrap_or_else(|| "-".to_string());
+        let [year, month, day, hour, minute, second] = utc_datetime(entry.time);
+        format!(
+            "{} - {} [{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000] \"{} {} {:?}\" {} {} \"{}\" \"{}\"",
+            self.field("client_ip", entry.client_ip.map(|ip| ip.to_string())).unwrap_or_else(|| "-".to_string()),
+            self.field("principal", entry.principal.clone()).unwrap_or_else(|| "-".to_string()),
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            hour,
+            minute,
+            second,
+            entry.method,
+            self.redact_query(&entry.uri),
+            entry.version,
+            entry.status,
+            match entry.bytes_out {
+                0 => "-".to_string(),
+                bytes => bytes.to_string(),
+            },
+            quoted(self.field("referer", entry.referer.clone())),
+            quoted(self.field("user_agent", entry.user_agent.clone())),
+        )
+    }
+
+    fn json(&self, entry: &Entry) -> String {
+        let mut object = Map::new();
+        let mut insert = |name: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                let value = if self.is_redacted(name) { Value::from(REDACTED) } else { value };
+                object.insert(name.to_string(), value);
+            }
+        };
+        insert("timestamp", Some(Value::from(entry.timestamp.clone())));
+        insert("request_id", entry.request_id.clone().map(Value::from));
+        insert("method", Some(Value::from(entry.method.clone())));
+        insert("path", Some(Value::from(self.redact_query(&entry.uri))));
+        insert("route", entry.route.clone().map(Value::from));
+        insert("protocol", Some(Value::from(format!("{:?}", entry.version))));
+        insert("status", Some(Value::from(entry.status)));
+        insert("latency_ms", Some(Value::from(entry.latency_ms)));
+        insert("bytes_in", Some(Value::from(entry.bytes_in)));
+        insert("bytes_out", Some(Value::from(entry.bytes_out)));
+        insert("client_ip", entry.client_ip.map(|ip| Value::from(ip.to_string())));
+        insert("user_agent", entry.user_agent.clone().map(Value::from));
+        insert("referer", entry.referer.clone().map(Value::from));
+        insert("principal", entry.principal.clone().map(Value::from));
+        insert("request_body", entry.request_body.clone().map(Value::from));
+        insert("response_body", entry.response_body.clone().map(Value::from));
+        Value::Object(object).to_string()
+    }
+}
+
+struct Entry {
+    time: u64,
+    timestamp: String,
+    request_id: Option<String>,
+    method: String,
+    uri: String,
+    route: Option<String>,
+    version: Version,
+    status: u16,
+    latency_ms: f64,
+    /// Request body bytes after decompression.
+    bytes_in: u64,
+    /// Response body bytes before compression.
+    bytes_out: u64,
+    client_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    principal: Option<String>,
+    request_body: Option<String>,
+    response_body: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum CaptureKind {
+    Json,
+    Text,
+}
+
+/// Counts the bytes of a body as it streams through, keeping up to `limit`
+/// of them when capturing.
+struct BodyRecorder {
+    bytes: AtomicU64,
+    capture: Option<CaptureKind>,
+    limit: usize,
+    captured: Mutex<Vec<u8>>,
+    truncated: AtomicBool,
+}
+
+impl BodyRecorder {
+    fn new(capture: Option<CaptureKind>, limit: usize) -> Self {
+        BodyRecorder {
+            bytes: AtomicU64::new(0),
+            capture,
+            limit,
+            captured: Mutex::new(Vec::new()),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    fn record(&self, data: &Bytes) {
+        self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        if self.capture.is_none() {
+            return;
+        }
+        let mut captured = self.captured.lock().unwrap();
+        let room = self.limit.saturating_sub(captured.len());
+        if data.len() > room {
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+        captured.extend_from_slice(&data[..data.len().min(room)]);
+    }
+}
+
+/// Hands the request's recorder to [`record_request_body`], so it sees the
+/// body as the handler does.
+#[derive(Clone)]
+struct RequestBodyRecorder(Arc<BodyRecorder>);
+
+/// Everything known about a request before its response body is sent.
+struct Pending {
+    log: Arc<AccessLog>,
+    started: Instant,
+    entry: Entry,
+    request_body: Arc<BodyRecorder>,
+}
+
+struct RecordedBody {
+    inner: Body,
+    recorder: Arc<BodyRecorder>,
+    /// Written when the body is dropped, whether it finished or the client
+    /// went away.
+    pending: Option<Pending>,
+}
+
+impl http_body::Body for RecordedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.get_mut();
+        let frame = Pin::new(&mut this.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                this.recorder.record(data);
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for RecordedBody {
+    fn drop(&mut self) {
+        if let Some(Pending { log, started, mut entry, request_body }) = self.pending.take() {
+            entry.latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            entry.bytes_in = request_body.bytes.load(Ordering::Relaxed);
+            entry.bytes_out = self.recorder.bytes.load(Ordering::Relaxed);
+            entry.request_body = log.render_body(&request_body);
+            entry.response_body = log.render_body(&self.recorder);
+            log.write(&entry);
+        }
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_owned)
+}
+
+/// Records each request in the access log. Must run inside
+/// [`crate::request_id::request_id`] and outside [`crate::auth::authenticate`].
+pub async fn access_log(State(log): State<Arc<AccessLog>>, request: Request, next: Next) -> Response {
+    if log.writers.is_empty() {
+        return next.run(request).await;
+    }
+
+    let started = Instant::now();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let headers = request.headers();
+    let entry = Entry {
+        time: now.as_secs(),
+        timestamp: timestamp(),
+        request_id: header_value(headers, header::HeaderName::from_static(REQUEST_ID_HEADER)),
+        method: request.method().to_string(),
+        uri: request.uri().path_and_query().map(|uri| uri.to_string()).unwrap_or_else(|| "/".to_string()),
+        route: request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned()),
+        version: request.version(),
+        status: 0,
+        latency_ms: 0.0,
+        bytes_in: 0,
+        bytes_out: 0,
+        client_ip: request_client_ip(request.extensions(), headers, &log.trusted_proxies),
+        user_agent: header_value(headers, header::USER_AGENT),
+        referer: header_value(headers, header::REFERER),
+        principal: None,
+        request_body: None,
+        response_body: None,
+    };
+
+    let request_body = Arc::new(BodyRecorder::new(log.capture_kind(headers), log.max_body_bytes));
+    let mut request = request;
+    request.extensions_mut().insert(RequestBodyRecorder(request_body.clone()));
+
+    let response = next.run(request).await;
+
+    let mut entry = entry;
+    entry.status = response.status().as_u16();
+    entry.principal = response.extensions().get::<Principal>().map(|principal| principal.name.clone());
+    let recorder = Arc::new(BodyRecorder::new(log.capture_kind(response.headers()), log.max_body_bytes));
+    let pending = Pending { log, started, entry, request_body };
+    response.map(|body| Body::new(RecordedBody { inner: body, recorder, pending: Some(pending) }))
+}
+
+/// Counts and captures the request body for [`access_log`]. Must run inside
+/// [`crate::compression::decompression_layer`], so `bytes_in` is the
+/// decompressed size like `bytes_out` is the size before compression.
+pub async fn record_request_body(mut request: Request, next: Next) -> Response {
+    let Some(RequestBodyRecorder(recorder)) = request.extensions_mut().remove::<RequestBodyRecorder>() else {
+        return next.run(request).await;
+    };
+    let request = request.map(|body| Body::new(RecordedBody { inner: body, recorder, pending: None }));
+    next.run(request).await
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    extract::{Extension, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::auth::{self, Principal};
+use crate::logging;
+use crate::reload::ConfigReloader;
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelRequest {
+    /// Full filter directives, e.g. `hello_cargo=debug,tower_http=info`.
+    pub directives: String,
+    /// Reverts to the previous default directives after this many seconds.
+    pub ttl_seconds: Option<u64>,
+}
+
+fn log_control_unavailable() -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Runtime log control is not available"})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    responses(
+        (status = 200, description = "Current log filter"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Admin access required")
+    )
+)]
+pub async fn get_log_level() -> impl IntoResponse {
+    match logging::log_level_controller() {
+        Some(controller) => (StatusCode::OK, Json(json!(controller.state()))),
+        None => log_control_unavailable(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/log-level",
+    responses(
+        (status = 200, description = "Log filter changed"),
+        (status = 400, description = "Invalid directives or ttl_seconds over a day"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Admin access required")
+    )
+)]
+pub async fn set_log_level(
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<LogLevelRequest>,
+) -> impl IntoResponse {
+    let Some(controller) = logging::log_level_controller() else {
+        return log_control_unavailable();
+    };
+
+    tracing::info!(principal = %principal.name, directives = %request.directives, "Log filter change requested");
+    let result = match request.ttl_seconds {
+        Some(ttl) => controller.set_temporarily(&request.directives, Duration::from_secs(ttl)),
+        None => controller.set(&request.directives),
+    };
+    match result {
+        Ok(state) => (StatusCode::OK, Json(json!(state))),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/config",
+    responses(
+        (status = 200, description = "Effective configuration, secrets redacted, with the layer each value came from"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Admin access required")
+    )
+)]
+pub async fn get_config(State(reloader): State<Arc<ConfigReloader>>) -> impl IntoResponse {
+    (StatusCode::OK, Json(json!(reloader.sources().annotate(&reloader.current()))))
+}
+
+/// What this binary was built from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// `unknown` when built outside a git checkout without `GIT_SHA` set.
+    pub git_sha: &'static str,
+    /// Enabled cargo features.
+    pub features: Vec<&'static str>,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        features: env!("CARGO_FEATURES").split(',').filter(|feature| !feature.is_empty()).collect(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/build",
+    responses(
+        (status = 200, description = "Version, git commit and enabled features"),
+        (status = 401, description = "Authentication required"),
+        (status = 403, description = "Admin access required")
+    )
+)]
+pub async fn get_build() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!(build_info())))
+}
+
+/// Admin endpoints. Callers must be authenticated as an admin principal.
+pub fn router(reloader: Arc<ConfigReloader>) -> Router {
+    Router::new()
+        .route("/admin/log-level", get(get_log_level).put(set_log_level))
+        .route("/admin/config", get(get_config))
+        .route("/admin/build", get(get_build))
+        .layer(axum::middleware::from_fn(auth::require_admin))
+        .with_state(reloader)
+}
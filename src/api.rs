@@ -1,22 +1,62 @@
+// Signals, Unix domain sockets and systemd integration have no portable
+// equivalent
+#[cfg(not(unix))]
+compile_error!("hello_cargo supports Unix-like systems only");
+
 mod services;
 pub mod repositories;
 mod models;
 mod schema;
+pub mod config;
+pub mod secret;
+pub mod auth;
+pub mod rate_limit;
+pub mod health;
+pub mod metrics;
+pub mod logging;
+pub mod telemetry;
+pub mod request_id;
+pub mod admin;
+pub mod access_log;
+pub mod limits;
+pub mod reload;
+pub mod migrations;
+pub mod cli;
+pub mod server;
+pub mod tls;
+pub mod cors;
+pub mod compression;
+pub mod security_headers;
+pub mod systemd;
 
 use axum::{
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Path, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
+    middleware::{from_fn, from_fn_with_state},
     routing::get,
-    Json, Router,
+    Extension, Json, Router,
 };
 use std::sync::Arc;
 use serde_json::json;
+use tokio::sync::Semaphore;
+use tower_http::trace::TraceLayer;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use services::UserService;
+use crate::access_log::AccessLog;
+use crate::auth::Authenticator;
+use crate::cors::Cors;
+use crate::limits::Limits;
+use crate::config::{AppConfig, ConfigSources, DocsMode, ListenerConfig, ListenerRole};
+use crate::metrics::Metrics;
+use crate::rate_limit::{RateLimiter, TrustedUnixPeer};
+use crate::reload::ConfigReloader;
+use crate::security_headers::SecurityHeaders;
 use crate::repositories::UserRepositoryArc;
+use crate::repositories::metered_repository::MeteredUserRepository;
+use crate::server::Shutdown;
 
 // Re-export User for use in tests
 pub use models::User;
@@ -25,16 +65,27 @@ pub struct AppState {
     user_service: Arc<UserService>,
 }
 
+/// A 500 for a failed repository read or delete. The cause is logged, not
+/// sent to the client.
+fn repository_error(error: String) -> (StatusCode, Json<serde_json::Value>) {
+    tracing::error!(%error, "Repository operation failed");
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Internal server error"})))
+}
+
 #[utoipa::path(
     get,
     path = "/users",
     responses(
-        (status = 200, description = "List of users", body = Vec<User>)
+        (status = 200, description = "List of users", body = Vec<User>),
+        (status = 500, description = "Users couldn't be loaded")
     )
 )]
+#[tracing::instrument(skip_all)]
 async fn get_users(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let users = state.user_service.get_all_users().await;
-    (StatusCode::OK, Json(users))
+    match state.user_service.get_all_users().await {
+        Ok(users) => (StatusCode::OK, Json(json!(users))),
+        Err(e) => repository_error(e),
+    }
 }
 
 #[utoipa::path(
@@ -42,17 +93,19 @@ async fn get_users(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     path = "/users/{user_id}",
     responses(
         (status = 200, description = "User found", body = User),
-        (status = 404, description = "User not found")
+        (status = 404, description = "User not found"),
+        (status = 500, description = "User couldn't be loaded")
     ),
     params(
         ("user_id" = String, Path, description = "User ID")
     )
 )]
+#[tracing::instrument(skip_all, fields(user_id = %user_id))]
 async fn get_user(State(state): State<Arc<AppState>>, Path(user_id): Path<String>) -> impl IntoResponse {
-    if let Some(user) = state.user_service.get_user(&user_id).await {
-        (StatusCode::OK, Json(json!(user)))
-    } else {
-        (StatusCode::NOT_FOUND, Json(json!({"error": "User not found"})))
+    match state.user_service.get_user(&user_id).await {
+        Ok(Some(user)) => (StatusCode::OK, Json(json!(user))),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(json!({"error": "User not found"}))),
+        Err(e) => repository_error(e),
     }
 }
 
@@ -65,6 +118,7 @@ async fn get_user(State(state): State<Arc<AppState>>, Path(user_id): Path<String
         (status = 400, description = "User ID already exists")
     )
 )]
+#[tracing::instrument(skip_all)]
 async fn create_user(State(state): State<Arc<AppState>>, Json(new_user): Json<User>) -> impl IntoResponse {
     match state.user_service.create_user(new_user).await {
         Ok(created_user) => (StatusCode::CREATED, Json(json!(created_user))),
@@ -85,6 +139,7 @@ async fn create_user(State(state): State<Arc<AppState>>, Json(new_user): Json<Us
         ("user_id" = String, Path, description = "User ID")
     )
 )]
+#[tracing::instrument(skip_all, fields(user_id = %user_id))]
 async fn update_user(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<String>,
@@ -104,17 +159,19 @@ async fn update_user(
     path = "/users/{user_id}",
     responses(
         (status = 200, description = "User deleted successfully"),
-        (status = 404, description = "User not found")
+        (status = 404, description = "User not found"),
+        (status = 500, description = "User couldn't be deleted")
     ),
     params(
         ("user_id" = String, Path, description = "User ID")
     )
 )]
-async fn delete_user(State(state): State<Arc<AppState>>, Path(user_id): Path<String>) -> impl IntoResponse {
-    if state.user_service.delete_user(&user_id).await {
-        StatusCode::OK
-    } else {
-        StatusCode::NOT_FOUND
+#[tracing::instrument(skip_all, fields(user_id = %user_id))]
+async fn delete_user(State(state): State<Arc<AppState>>, Path(user_id): Path<String>) -> Response {
+    match state.user_service.delete_user(&user_id).await {
+        Ok(true) => StatusCode::OK.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => repository_error(e).into_response(),
     }
 }
 
@@ -125,7 +182,13 @@ async fn delete_user(State(state): State<Arc<AppState>>, Path(user_id): Path<Str
         get_user,
         create_user,
         update_user,
-        delete_user
+        delete_user,
+        health::live,
+        health::ready,
+        admin::get_log_level,
+        admin::set_log_level,
+        admin::get_config,
+        admin::get_build
     ),
     components(
         schemas(User)
@@ -136,13 +199,150 @@ async fn delete_user(State(state): State<Arc<AppState>>, Path(user_id): Path<Str
 )]
 struct ApiDoc;
 
-pub fn app(user_repository: UserRepositoryArc) -> Router {
-    let user_service = Arc::new(UserService::new(user_repository));
+/// The OpenAPI spec served at `/api-docs/openapi.json`.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+/// Templates of the routes served, as `server.routes` and
+/// `rate_limit.routes` name them. Swagger UI's assets are left out.
+pub const ROUTES: &[&str] = &[
+    "/users",
+    "/users/:id",
+    "/api-docs/openapi.json",
+    "/health/live",
+    "/health/ready",
+    "/metrics",
+    "/admin/log-level",
+    "/admin/config",
+    "/admin/build",
+];
+
+/// The routers together with the handles the server drives them through.
+pub struct App {
+    /// Public and admin routes together, for a listener serving both.
+    pub router: Router,
+    /// The user API and its docs.
+    pub public_router: Router,
+    /// Health probes, metrics and `/admin`.
+    pub admin_router: Router,
+    /// Applies reloaded configuration to the running routers.
+    pub reloader: Arc<ConfigReloader>,
+    /// Flips readiness when the server starts shutting down.
+    pub shutdown: Shutdown,
+}
+
+impl App {
+    /// The router for `listener`: the routes it serves, with its peers
+    /// marked as trusted proxies if it says so.
+    pub fn router_for(&self, listener: &ListenerConfig) -> Router {
+        let router = match listener.serves {
+            ListenerRole::Public => self.public_router.clone(),
+            ListenerRole::Admin => self.admin_router.clone(),
+            ListenerRole::Both => self.router.clone(),
+        };
+        if listener.trust_forwarded {
+            router.layer(Extension(TrustedUnixPeer))
+        } else {
+            router
+        }
+    }
+}
+
+pub fn app(user_repository: UserRepositoryArc, config: &AppConfig) -> Router {
+    build_app(user_repository, config, ConfigSources::default()).router
+}
+
+/// Builds the app for `config`; `sources` records where each of its values
+/// came from, for `GET /admin/config`.
+pub fn build_app(user_repository: UserRepositoryArc, config: &AppConfig, sources: ConfigSources) -> App {
+    let metrics = Arc::new(Metrics::new());
+    let user_repository: UserRepositoryArc = Arc::new(MeteredUserRepository::new(user_repository, metrics.clone()));
+    let user_service = Arc::new(UserService::new(user_repository.clone()));
     let app_state = Arc::new(AppState { user_service });
+    let authenticator = Arc::new(Authenticator::new(config.auth.clone()));
+    let admin_authenticator = Arc::new(Authenticator::admin(config.auth.clone()));
+    let rate_limiter = Arc::new(RateLimiter::new(config.rate_limit.clone()));
+    let cors = Arc::new(Cors::new(config.cors.clone()));
+    let access_log = AccessLog::new(&config.log.access).unwrap_or_else(|error| {
+        tracing::error!(%error, "Failed to open access log sinks; access log disabled");
+        AccessLog::disabled()
+    });
+    let reloader = Arc::new(ConfigReloader::new(
+        config.clone(),
+        sources,
+        [authenticator.clone(), admin_authenticator.clone()],
+        rate_limiter.clone(),
+        cors.clone(),
+    ));
+    let shutdown = Shutdown::new();
+
+    let limits = Arc::new(Limits::new(&config.server));
+    let permits = Arc::new(Semaphore::new(config.server.max_concurrent_requests));
+    let accepted_encodings = compression::accepted_encodings(&config.server.compression);
+    let guarded = |router: Router, authenticator: &Arc<Authenticator>| {
+        router
+            // Counts bodies as decompressed, so a small compressed body
+            // can't inflate past the limit
+            .layer(from_fn_with_state(limits.clone(), limits::limit))
+            // Replaced by the per-route limit above
+            .layer(DefaultBodyLimit::disable())
+            .layer(from_fn(access_log::record_request_body))
+            .layer(compression::decompression_layer(&accepted_encodings))
+            .layer(from_fn_with_state(accepted_encodings.clone(), compression::content_encoding))
+            // Layers run bottom-up: authentication must resolve the principal
+            // before the rate limiter picks a bucket for it.
+            .layer(from_fn_with_state(rate_limiter.clone(), rate_limit::rate_limit))
+            .layer(from_fn_with_state(authenticator.clone(), auth::authenticate))
+            // First, so shed requests cost next to nothing
+            .layer(from_fn_with_state(permits.clone(), limits::shed_load))
+    };
+    let access_log = Arc::new(access_log);
+    let security_headers = Arc::new(SecurityHeaders::new(&config.server.security_headers));
+    let outer = |router: Router| {
+        router
+            // Inside the logging layers, so they record the 500
+            .layer(limits::catch_panic())
+            .layer(from_fn_with_state(security_headers.clone(), security_headers::security_headers))
+            // Outside authentication, which preflights can't pass
+            .layer(from_fn_with_state(cors.clone(), cors::cors))
+            .layer(from_fn_with_state(metrics.clone(), metrics::track_http))
+            .layer(from_fn_with_state(access_log.clone(), access_log::access_log))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(telemetry::make_request_span)
+                    .on_response(telemetry::on_response),
+            )
+            // Outside the trace layer, so its span can record the id
+            .layer(from_fn(request_id::request_id))
+            // Compresses the finished body, request id included
+            .layer(compression::compression_layer(&config.server.compression))
+    };
+
+    let docs = Router::new().merge(SwaggerUi::new(security_headers::DOCS_PATH).url("/api-docs/openapi.json", openapi()));
+    let docs = match config.docs.mode {
+        DocsMode::Public => docs,
+        DocsMode::Authenticated => docs.layer(from_fn(auth::require_principal)),
+        DocsMode::Disabled => Router::new(),
+    };
+    let public = guarded(
+        Router::new()
+            .merge(docs)
+            .route("/users", get(get_users).post(create_user))
+            .route("/users/:id", get(get_user).put(update_user).delete(delete_user))
+            .with_state(app_state),
+        &authenticator,
+    );
+    // A separate authenticator, so admin-only keys don't open the public API.
+    // Only the probes stay open, for orchestrators that can't authenticate.
+    let admin = guarded(
+        admin::router(reloader.clone()).merge(metrics::router(metrics.clone(), user_repository.clone())),
+        &admin_authenticator,
+    )
+    .merge(health::router(user_repository, shutdown.clone()));
 
-    Router::new()
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .route("/users", get(get_users).post(create_user))
-        .route("/users/:id", get(get_user).put(update_user).delete(delete_user))
-        .with_state(app_state)
+    let public_router = outer(public.clone());
+    let admin_router = outer(admin.clone());
+    let router = outer(public.merge(admin));
+    App { router, public_router, admin_router, reloader, shutdown }
 }
\ No newline at end of file
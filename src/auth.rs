@@ -0,0 +1,134 @@
+use std::sync::{Arc, RwLock};
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::config::AuthConfig;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrincipalKind {
+    ApiKey,
+    ClientCertificate,
+}
+
+/// The authenticated caller, stored in request extensions by [`authenticate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+    pub kind: PrincipalKind,
+    pub admin: bool,
+}
+
+/// The principal name from a verified client certificate, stored in request
+/// extensions by the server for mutual TLS connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub name: String,
+}
+
+pub struct Authenticator {
+    config: RwLock<AuthConfig>,
+    /// Whether `admin_api_keys` are accepted.
+    admin_routes: bool,
+}
+
+impl Authenticator {
+    /// Authenticates callers of the public API.
+    pub fn new(config: AuthConfig) -> Self {
+        Authenticator { config: RwLock::new(config), admin_routes: false }
+    }
+
+    /// Authenticates callers of the admin routes, additionally accepting
+    /// `admin_api_keys`.
+    pub fn admin(config: AuthConfig) -> Self {
+        Authenticator { config: RwLock::new(config), admin_routes: true }
+    }
+
+    /// Swaps in new API keys and admin certificates; requests already authenticated keep
+    /// their principal.
+    pub fn update(&self, config: AuthConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    fn resolve_api_key(&self, key: &str) -> Option<Principal> {
+        let config = self.config.read().unwrap();
+        let admin_api_keys = config.admin_api_keys.iter().filter(|_| self.admin_routes);
+        config
+            .api_keys
+            .iter()
+            .map(|api_key| (api_key, api_key.admin))
+            .chain(admin_api_keys.map(|api_key| (api_key, true)))
+            .find(|(api_key, _)| api_key.key.expose() == key)
+            .map(|(api_key, admin)| Principal {
+                name: api_key.name.clone(),
+                kind: PrincipalKind::ApiKey,
+                admin,
+            })
+    }
+
+    fn resolve_certificate(&self, certificate: &ClientCertificate) -> Principal {
+        Principal {
+            name: certificate.name.clone(),
+            kind: PrincipalKind::ClientCertificate,
+            admin: self.config.read().unwrap().admin_certificates.contains(&certificate.name),
+        }
+    }
+}
+
+/// Attaches a [`Principal`] to requests carrying a known API key or a
+/// verified client certificate, and to their responses for outer layers such
+/// as the access log. An API key wins over a certificate. Requests with
+/// neither pass through anonymously; an unknown key is rejected.
+pub async fn authenticate(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let mut principal = None;
+    if let Some(value) = request.headers().get(API_KEY_HEADER) {
+        principal = value.to_str().ok().and_then(|key| authenticator.resolve_api_key(key));
+        match &principal {
+            Some(principal) => {
+                request.extensions_mut().insert(principal.clone());
+            }
+            None => {
+                return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid API key"}))).into_response();
+            }
+        }
+    } else if let Some(certificate) = request.extensions().get::<ClientCertificate>() {
+        let resolved = authenticator.resolve_certificate(certificate);
+        request.extensions_mut().insert(resolved.clone());
+        principal = Some(resolved);
+    }
+    let mut response = next.run(request).await;
+    if let Some(principal) = principal {
+        response.extensions_mut().insert(principal);
+    }
+    response
+}
+
+/// Rejects requests that were not authenticated. Must run after
+/// [`authenticate`].
+pub async fn require_principal(request: Request, next: Next) -> Response {
+    if request.extensions().get::<Principal>().is_none() {
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Authentication required"}))).into_response();
+    }
+    next.run(request).await
+}
+
+/// Rejects requests that were not authenticated as an admin principal.
+/// Must run after [`authenticate`].
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    match request.extensions().get::<Principal>() {
+        Some(principal) if principal.admin => next.run(request).await,
+        Some(_) => (StatusCode::FORBIDDEN, Json(json!({"error": "Admin access required"}))).into_response(),
+        None => (StatusCode::UNAUTHORIZED, Json(json!({"error": "Authentication required"}))).into_response(),
+    }
+}
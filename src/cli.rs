@@ -0,0 +1,317 @@
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use diesel::{Connection, PgConnection};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::config::{self, AppConfig, ConfigError, ConfigSources};
+use crate::migrations;
+use crate::repositories::postgres_repository::PostgresUserRepository;
+use crate::repositories::UserRepository;
+use crate::User;
+
+pub mod serve;
+
+/// Exit codes, following sysexits(3) where one fits. Usage errors exit with
+/// 2, as reported by clap.
+pub const EXIT_OK: u8 = 0;
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_UNAVAILABLE: u8 = 69;
+pub const EXIT_CONFIG: u8 = 78;
+
+#[derive(Debug, Parser)]
+#[command(name = "hello_cargo", version, about = "User management API")]
+pub struct Cli {
+    /// Directory holding default.toml, default.{RUN_MODE}.toml and local.toml
+    #[arg(long, global = true, default_value = "config")]
+    pub config_dir: PathBuf,
+    /// Format of command results; errors go to stderr in the same format
+    #[arg(long, short, global = true, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server
+    Serve(ServeArgs),
+    /// Manage database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print the OpenAPI spec as JSON
+    Openapi,
+    /// Manage users directly in the configured database
+    #[command(subcommand)]
+    Users(UsersCommand),
+}
+
+/// Source reported for values set by command-line options.
+pub(crate) const COMMAND_LINE: &str = "command line";
+
+/// Overrides for the loaded configuration.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ServeArgs {
+    #[arg(long)]
+    pub host: Option<IpAddr>,
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Log level for the application, e.g. DEBUG
+    #[arg(long)]
+    pub log_level: Option<String>,
+}
+
+impl ServeArgs {
+    /// Overrides `config`, recording the command line as the source of
+    /// each value changed.
+    pub fn apply(&self, config: &mut AppConfig, sources: &mut ConfigSources) {
+        if let Some(host) = self.host {
+            config.server.host = host;
+            sources.set("server.host", COMMAND_LINE);
+        }
+        if let Some(port) = self.port {
+            config.server.port = port;
+            sources.set("server.port", COMMAND_LINE);
+        }
+        if let Some(log_level) = &self.log_level {
+            config.log.level = log_level.clone();
+            sources.set("log.level", COMMAND_LINE);
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// Revert applied migrations, newest first
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List applied, pending and unknown migrations; exits with 1 unless the
+    /// schema matches this binary
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate the configuration, listing every problem
+    Check,
+    /// Print the effective configuration with secrets redacted
+    Print,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UsersCommand {
+    List,
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        email: String,
+        /// Generated when omitted
+        #[arg(long)]
+        id: Option<String>,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+/// Runs `cli`, writing results to `out`, and returns the exit code.
+pub async fn run(cli: Cli, out: &mut dyn Write) -> u8 {
+    let command = cli.command.unwrap_or(Command::Serve(ServeArgs::default()));
+    let output = cli.output;
+
+    let (mut config, mut sources) = match config::load_config_with_sources(&cli.config_dir, &config::run_mode(), None) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            return match command {
+                Command::Config(ConfigCommand::Check) => config_problems(out, output, &error),
+                // The spec doesn't depend on configuration
+                Command::Openapi => openapi(out),
+                _ => fail(output, EXIT_CONFIG, &error.to_string()),
+            };
+        }
+    };
+
+    match command {
+        Command::Serve(args) => {
+            args.apply(&mut config, &mut sources);
+            if let Err(error) = config::revalidate(&config, COMMAND_LINE) {
+                return fail(output, EXIT_CONFIG, &error.to_string());
+            }
+            match serve::serve(config, sources, args, cli.config_dir).await {
+                Ok(()) => EXIT_OK,
+                Err(error) => fail(output, EXIT_FAILURE, &error.to_string()),
+            }
+        }
+        Command::Migrate(command) => migrate(out, output, &config, command),
+        Command::Config(ConfigCommand::Check) => {
+            let result = match output {
+                Output::Text => writeln!(out, "Configuration is valid"),
+                Output::Json => writeln!(out, "{}", json!({"valid": true, "problems": []})),
+            };
+            written(output, result, EXIT_OK)
+        }
+        Command::Config(ConfigCommand::Print) => {
+            let result = match output {
+                Output::Text => match toml::to_string_pretty(&config) {
+                    Ok(toml) => write!(out, "{}", toml),
+                    Err(error) => return fail(output, EXIT_FAILURE, &error.to_string()),
+                },
+                Output::Json => print_json(out, &config),
+            };
+            written(output, result, EXIT_OK)
+        }
+        Command::Openapi => openapi(out),
+        Command::Users(command) => users(out, output, &config, command).await,
+    }
+}
+
+/// Reports a failure on stderr and returns `code`.
+fn fail(output: Output, code: u8, message: &str) -> u8 {
+    match output {
+        Output::Text => eprintln!("Error: {}", message),
+        Output::Json => eprintln!("{}", json!({"error": message})),
+    }
+    code
+}
+
+fn written(output: Output, result: std::io::Result<()>, code: u8) -> u8 {
+    match result {
+        Ok(()) => code,
+        Err(error) => fail(output, EXIT_FAILURE, &error.to_string()),
+    }
+}
+
+fn print_json(out: &mut dyn Write, value: &impl Serialize) -> std::io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, value)?;
+    writeln!(out)
+}
+
+fn config_problems(out: &mut dyn Write, output: Output, error: &ConfigError) -> u8 {
+    let result = match (output, error) {
+        (Output::Json, ConfigError::Invalid(problems)) => print_json(out, &json!({"valid": false, "problems": problems})),
+        (Output::Json, ConfigError::Load(error)) => {
+            print_json(out, &json!({"valid": false, "problems": [{"key": "", "source": "", "message": error.to_string()}]}))
+        }
+        (Output::Text, error) => writeln!(out, "{}", error),
+    };
+    written(output, result, EXIT_CONFIG)
+}
+
+fn openapi(out: &mut dyn Write) -> u8 {
+    match crate::openapi().to_pretty_json() {
+        Ok(spec) => written(Output::Text, writeln!(out, "{}", spec), EXIT_OK),
+        Err(error) => fail(Output::Text, EXIT_FAILURE, &error.to_string()),
+    }
+}
+
+fn migrate(out: &mut dyn Write, output: Output, config: &AppConfig, command: MigrateCommand) -> u8 {
+    let connection = config
+        .database
+        .connection_url()
+        .and_then(|url| PgConnection::establish(url.expose()).map_err(|error| error.to_string()));
+    let conn = &mut match connection {
+        Ok(conn) => conn,
+        Err(error) => return fail(output, EXIT_UNAVAILABLE, &format!("Cannot connect to the database: {}", error)),
+    };
+
+    let (result, code) = match command {
+        MigrateCommand::Up => match migrations::up(conn) {
+            Ok(applied) => (names(out, output, "Applied", "applied", &applied), EXIT_OK),
+            Err(error) => return fail(output, EXIT_FAILURE, &error.to_string()),
+        },
+        MigrateCommand::Down { steps } => match migrations::down(conn, steps) {
+            Ok(reverted) => (names(out, output, "Reverted", "reverted", &reverted), EXIT_OK),
+            Err(error) => return fail(output, EXIT_FAILURE, &error.to_string()),
+        },
+        MigrateCommand::Status => match migrations::status(conn) {
+            Ok(report) => {
+                let code = if report.check().is_ok() { EXIT_OK } else { EXIT_FAILURE };
+                let result = match output {
+                    Output::Text => report
+                        .applied
+                        .iter()
+                        .map(|name| ("applied", name))
+                        .chain(report.pending.iter().map(|name| ("pending", name)))
+                        .chain(report.unknown.iter().map(|version| ("unknown", version)))
+                        .try_for_each(|(state, name)| writeln!(out, "{}  {}", state, name)),
+                    Output::Json => print_json(out, &report),
+                };
+                (result, code)
+            }
+            Err(error) => return fail(output, EXIT_FAILURE, &error.to_string()),
+        },
+    };
+    written(output, result, code)
+}
+
+fn names(out: &mut dyn Write, output: Output, verb: &str, key: &str, names: &[String]) -> std::io::Result<()> {
+    match output {
+        Output::Text if names.is_empty() => writeln!(out, "Nothing to do"),
+        Output::Text => names.iter().try_for_each(|name| writeln!(out, "{} {}", verb, name)),
+        Output::Json => print_json(out, &json!({ key: names })),
+    }
+}
+
+fn user_line(out: &mut dyn Write, user: &User) -> std::io::Result<()> {
+    writeln!(out, "{}\t{}\t{}", user.id, user.name, user.email)
+}
+
+async fn users(out: &mut dyn Write, output: Output, config: &AppConfig, command: UsersCommand) -> u8 {
+    let repository = match PostgresUserRepository::connect(&config.database) {
+        Ok(repository) => repository,
+        Err(error) => return fail(output, EXIT_UNAVAILABLE, &format!("Cannot connect to the database: {}", error)),
+    };
+
+    match command {
+        UsersCommand::List => {
+            let users = match repository.list() {
+                Ok(users) => users,
+                Err(error) => return fail(output, EXIT_UNAVAILABLE, &error),
+            };
+            let result = match output {
+                Output::Text => users.iter().try_for_each(|user| user_line(out, user)),
+                Output::Json => print_json(out, &users),
+            };
+            written(output, result, EXIT_OK)
+        }
+        UsersCommand::Create { name, email, id } => match repository.create(User::new(id, name, email)).await {
+            Ok(user) => {
+                let result = match output {
+                    Output::Text => user_line(out, &user),
+                    Output::Json => print_json(out, &user),
+                };
+                written(output, result, EXIT_OK)
+            }
+            Err(error) => fail(output, EXIT_FAILURE, &error),
+        },
+        UsersCommand::Delete { id } => {
+            match repository.remove(&id) {
+                Ok(true) => {}
+                Ok(false) => return fail(output, EXIT_FAILURE, "User not found"),
+                Err(error) => return fail(output, EXIT_UNAVAILABLE, &error),
+            }
+            let result = match output {
+                Output::Text => writeln!(out, "Deleted {}", id),
+                Output::Json => print_json(out, &json!({"deleted": id})),
+            };
+            written(output, result, EXIT_OK)
+        }
+    }
+}
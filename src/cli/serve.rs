@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use diesel::{Connection, PgConnection};
+use tracing::{debug, info, warn};
+
+use crate::build_app;
+use crate::config::{self, AppConfig, ConfigSources};
+use crate::logging;
+use crate::migrations;
+use crate::reload;
+use crate::repositories::postgres_repository::PostgresUserRepository;
+use crate::repositories::UserRepositoryArc;
+use crate::server::{self, Listener, ShutdownSignals};
+use crate::systemd::{self, ActivatedSockets, Notifier};
+use crate::tls::{self, CertStore};
+use super::{ServeArgs, COMMAND_LINE};
+
+/// How often `config/*.toml` is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the TLS certificate and key are checked for rotation.
+const CERT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs the HTTP server until SIGTERM or SIGINT, then shuts down in order:
+/// readiness fails, connections drain, background tasks stop and the
+/// database pool closes.
+pub async fn serve(
+    config: AppConfig,
+    sources: ConfigSources,
+    args: ServeArgs,
+    config_dir: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let _logging = logging::setup_logging(&config.log)?;
+    let mut signals = ShutdownSignals::new()?;
+
+    info!("Starting application");
+    debug!("Loaded configuration: {:?}", config);
+
+    {
+        let conn = &mut PgConnection::establish(config.database.connection_url()?.expose())?;
+        migrations::prepare(conn, config.database.auto_migrate).map_err(|error| error as Box<dyn std::error::Error>)?;
+    }
+    info!("Database schema is up to date");
+
+    let repository = Arc::new(PostgresUserRepository::connect(&config.database)?);
+
+    let app = build_app(repository.clone() as UserRepositoryArc, &config, sources);
+    let reloader = app.reloader.clone();
+    let watcher = tokio::spawn(async move {
+        let run_mode = config::run_mode();
+        let load = {
+            let config_dir = config_dir.clone();
+            // Command-line overrides keep winning over reloaded files
+            move || {
+                let (mut config, mut sources) = config::load_config_with_sources(&config_dir, &run_mode, None)?;
+                args.apply(&mut config, &mut sources);
+                config::revalidate(&config, COMMAND_LINE)?;
+                Ok((config, sources))
+            }
+        };
+        if let Err(error) = reload::watch(reloader, config_dir, CONFIG_POLL_INTERVAL, load).await {
+            warn!("Configuration hot reload unavailable: {}", error);
+        }
+    });
+
+    let shutdown = app.shutdown.clone();
+    let drain_deadline = Duration::from_secs(config.server.shutdown_grace_seconds);
+    let mut servers = Vec::new();
+    let mut cert_watcher = None;
+
+    let env: HashMap<String, String> = std::env::vars().collect();
+    let notifier = Arc::new(Notifier::from_env(&env).unwrap_or_else(|error| {
+        warn!(%error, "Can't notify systemd; carrying on without it");
+        Notifier::disabled()
+    }));
+    // SAFETY: the only call, and nothing else touches inherited descriptors
+    let mut activated = unsafe { ActivatedSockets::from_env(&env)? };
+    let listeners = config.server.effective_listeners(&activated.names());
+    let mut bound = Vec::new();
+    for listener_config in &listeners {
+        let listener = match &listener_config.systemd {
+            Some(name) => {
+                let fd = activated.take(name).ok_or_else(|| format!("systemd passed no socket named {}", name))?;
+                Listener::from_fd(fd)?
+            }
+            None => Listener::bind(listener_config).await?,
+        };
+        bound.push((listener_config, listener));
+    }
+    let tls = match &config.server.tls {
+        Some(tls) => {
+            let store = Arc::new(CertStore::load_for(tls, config.server.http.protocol)?);
+            cert_watcher = Some(tokio::spawn(tls::watch(store.clone(), CERT_POLL_INTERVAL)));
+            if let Some(redirect_port) = tls.redirect_port {
+                // From the socket, so systemd-passed listeners count too
+                let https_port = bound.iter().filter(|(config, _)| config.tls).find_map(|(_, listener)| listener.tcp_port());
+                let https_port = https_port.ok_or("server.tls.redirect_port is set, but no TLS listener is on TCP")?;
+                // Bound here rather than passed by systemd
+                let redirect_addr = SocketAddr::new(config.server.host, redirect_port);
+                let redirect_listener = tokio::net::TcpListener::bind(redirect_addr).await?;
+                info!("Redirecting HTTP on {} to HTTPS", redirect_addr);
+                let redirect = tls::redirect_router(https_port);
+                let http = tls::redirect_http_config(&config.server.http);
+                servers.push(tokio::spawn(server::serve(redirect_listener, None, redirect, shutdown.clone(), drain_deadline, http)));
+            }
+            Some(store)
+        }
+        None => None,
+    };
+    for (listener_config, listener) in bound {
+        let serves = listener_config.serves;
+        info!("Listening on {} ({:?} routes{})", listener, serves, if listener_config.tls { ", TLS" } else { "" });
+        let tls = tls.clone().filter(|_| listener_config.tls);
+        servers.push(tokio::spawn(server::serve(listener, tls, app.router_for(listener_config), shutdown.clone(), drain_deadline, config.server.http.clone())));
+    }
+    // The servers hold the routers now; the pool closes once they finish
+    drop(app);
+    if !activated.names().is_empty() {
+        warn!(sockets = ?activated.names(), "Ignoring sockets passed by systemd that no listener uses");
+    }
+    if let Err(error) = notifier.ready() {
+        warn!(%error, "Failed to notify systemd of readiness");
+    }
+    let watchdog = tokio::spawn(systemd::watchdog(notifier.clone()));
+
+    let signal = signals.recv().await;
+    info!("Received {}, shutting down", signal);
+    if let Err(error) = notifier.stopping() {
+        warn!(%error, "Failed to notify systemd of shutdown");
+    }
+    shutdown.begin();
+    info!("Readiness probe now failing");
+    for server in servers {
+        server.await?;
+    }
+
+    watcher.abort();
+    watchdog.abort();
+    if let Some(cert_watcher) = cert_watcher {
+        cert_watcher.abort();
+    }
+    info!("Stopped configuration and certificate watchers");
+
+    // Every connection task has ended, so this is the last reference
+    match Arc::try_unwrap(repository) {
+        Ok(repository) => {
+            drop(repository);
+            info!("Closed database pool");
+        }
+        Err(_) => warn!("Database pool still in use; it will close on exit"),
+    }
+
+    info!("Application shutdown complete");
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Request, State},
+    http::{self, header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http_body::Body;
+use serde_json::json;
+use tower_http::compression::{predicate::Predicate, CompressionLayer};
+use tower_http::decompression::RequestDecompressionLayer;
+use tower_http::CompressionLevel as Quality;
+
+use crate::config::{CompressionConfig, CompressionLevel, Encoding};
+
+/// Compresses responses of the configured types and minimum size.
+#[derive(Debug, Clone)]
+pub struct CompressWhen {
+    enabled: bool,
+    min_size_bytes: u64,
+    content_types: Arc<[String]>,
+}
+
+impl CompressWhen {
+    fn content_type_matches(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        self.content_types.iter().any(|pattern| match pattern.strip_suffix("/*") {
+            Some(kind) => essence.split_once('/').is_some_and(|(essence_kind, _)| essence_kind == kind),
+            None => essence == *pattern,
+        })
+    }
+}
+
+impl Predicate for CompressWhen {
+    fn should_compress<B: Body>(&self, response: &http::Response<B>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let size = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse().ok())
+        });
+        // Streamed bodies of unknown size are worth compressing
+        if size.is_some_and(|size| size < self.min_size_bytes) {
+            return false;
+        }
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| self.content_type_matches(content_type))
+    }
+}
+
+/// Negotiates response compression with `Accept-Encoding`.
+pub fn compression_layer(config: &CompressionConfig) -> CompressionLayer<CompressWhen> {
+    let quality = match config.level {
+        CompressionLevel::Fastest => Quality::Fastest,
+        CompressionLevel::Default => Quality::Default,
+        CompressionLevel::Best => Quality::Best,
+    };
+    CompressionLayer::new()
+        .gzip(config.encodings.contains(&Encoding::Gzip))
+        .br(config.encodings.contains(&Encoding::Br))
+        .zstd(config.encodings.contains(&Encoding::Zstd))
+        .no_deflate()
+        .quality(quality)
+        .compress_when(CompressWhen {
+            enabled: config.enabled,
+            min_size_bytes: config.min_size_bytes,
+            content_types: config.content_types.iter().map(|content_type| content_type.to_ascii_lowercase()).collect(),
+        })
+}
+
+/// The encodings request bodies may arrive in.
+pub fn accepted_encodings(config: &CompressionConfig) -> Arc<[Encoding]> {
+    if config.decompress_requests {
+        config.encodings.iter().copied().collect()
+    } else {
+        Arc::new([])
+    }
+}
+
+/// Decompresses request bodies as they're read. Must run inside
+/// [`content_encoding`], which turns away encodings it can't handle.
+pub fn decompression_layer(accepted: &[Encoding]) -> RequestDecompressionLayer {
+    RequestDecompressionLayer::new()
+        .gzip(accepted.contains(&Encoding::Gzip))
+        .br(accepted.contains(&Encoding::Br))
+        .zstd(accepted.contains(&Encoding::Zstd))
+        .no_deflate()
+}
+
+/// Rejects request bodies in an encoding other than `accepted` with 415,
+/// listing the accepted ones in `Accept-Encoding`.
+pub async fn content_encoding(State(accepted): State<Arc<[Encoding]>>, request: Request, next: Next) -> Response {
+    let supported = match request.headers().get(header::CONTENT_ENCODING) {
+        None => true,
+        // Matched exactly, as the decompression layer does
+        Some(encoding) => encoding == "identity" || accepted.iter().any(|accepted| encoding == accepted.as_str()),
+    };
+    if supported {
+        return next.run(request).await;
+    }
+
+    let accept = accepted.iter().map(|encoding| encoding.as_str()).chain(["identity"]).collect::<Vec<_>>().join(", ");
+    let mut response =
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(json!({"error": "Unsupported Content-Encoding"}))).into_response();
+    if let Ok(accept) = HeaderValue::from_str(&accept) {
+        response.headers_mut().insert(header::ACCEPT_ENCODING, accept);
+    }
+    response
+}
@@ -1,40 +1,1093 @@
-use std::net::IpAddr;
-use config::{Config, File, Environment};
-use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use config::{Config, File, Environment, Source};
+use ipnet::IpNet;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+use crate::secret::Secret;
+
+mod resolve;
+mod validate;
+
+pub(crate) use resolve::read_secret;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub log: LogConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub docs: DocsConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ServerConfig {
     pub host: IpAddr,
     pub port: u16,
+    /// Requests still running after this long are answered with 408 if the
+    /// handler is waiting on the body, 504 otherwise.
+    #[serde(default = "default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+    /// Largest request body accepted, in bytes. Compressed bodies are
+    /// held to this after decompression.
+    #[serde(default = "default_body_limit_bytes")]
+    pub body_limit_bytes: usize,
+    /// How long in-flight requests get to drain after SIGTERM or SIGINT
+    /// before their connections are closed.
+    #[serde(default = "default_shutdown_grace_seconds")]
+    pub shutdown_grace_seconds: u64,
+    /// Serve HTTPS instead of plain HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Where to listen. When empty, a single listener on `host:port` serves
+    /// every route, over TLS if `tls` is set.
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    /// Requests handled at once; more are shed with 503. Health probes and
+    /// metrics aren't counted.
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// Per-route overrides of the body limit and timeout. The first entry
+    /// matching a request applies.
+    #[serde(default)]
+    pub routes: Vec<RouteLimitConfig>,
+    #[serde(default)]
+    pub security_headers: SecurityHeadersConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+}
+
+impl ServerConfig {
+    /// `listeners`; failing that, one listener per socket systemd passed
+    /// in (named `activated`), or else the single listener `host:port`
+    /// implies.
+    pub fn effective_listeners(&self, activated: &[String]) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+        let listener = ListenerConfig {
+            tcp: None,
+            unix: None,
+            systemd: None,
+            mode: None,
+            serves: ListenerRole::Both,
+            tls: self.tls.is_some(),
+            trust_forwarded: false,
+        };
+        if !activated.is_empty() {
+            return activated.iter().map(|name| ListenerConfig { systemd: Some(name.clone()), ..listener.clone() }).collect();
+        }
+        vec![ListenerConfig { tcp: Some(SocketAddr::new(self.host, self.port)), ..listener }]
+    }
+}
+
+/// A TCP address, Unix socket path or systemd-passed socket to serve some
+/// of the routes on. Exactly one of `tcp`, `unix` and `systemd` must be set.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ListenerConfig {
+    #[serde(default)]
+    pub tcp: Option<SocketAddr>,
+    #[serde(default)]
+    pub unix: Option<PathBuf>,
+    /// The `FileDescriptorName=` of a socket passed in by systemd socket
+    /// activation.
+    #[serde(default)]
+    pub systemd: Option<String>,
+    /// Permissions for the Unix socket file, in octal, e.g. "660".
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub serves: ListenerRole,
+    /// Terminate TLS on this listener using `server.tls`.
+    #[serde(default)]
+    pub tls: bool,
+    /// Treat every peer on this Unix socket as a trusted proxy, taking the
+    /// client address from its `X-Forwarded-For`, e.g. for a sidecar.
+    #[serde(default)]
+    pub trust_forwarded: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// The user API and its docs.
+    Public,
+    /// Health probes, metrics and `/admin`.
+    Admin,
+    #[default]
+    Both,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8080,
+            request_timeout_seconds: default_request_timeout_seconds(),
+            body_limit_bytes: default_body_limit_bytes(),
+            shutdown_grace_seconds: default_shutdown_grace_seconds(),
+            tls: None,
+            listeners: Vec::new(),
+            compression: CompressionConfig::default(),
+            max_concurrent_requests: default_max_concurrent_requests(),
+            routes: Vec::new(),
+            security_headers: SecurityHeadersConfig::default(),
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+fn default_request_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_body_limit_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_shutdown_grace_seconds() -> u64 {
+    30
+}
+
+fn default_max_concurrent_requests() -> usize {
+    1024
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RouteLimitConfig {
+    /// Route template as registered on the router, e.g. `/users/:id`.
+    pub path: String,
+    /// Restricts the override to one method; applies to all methods when absent.
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub body_limit_bytes: Option<usize>,
+    #[serde(default)]
+    pub request_timeout_seconds: Option<u64>,
+}
+
+/// Response compression, negotiated with `Accept-Encoding`, and
+/// decompression of request bodies sent with `Content-Encoding`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Encodings offered for responses and accepted for request bodies.
+    pub encodings: Vec<Encoding>,
+    pub level: CompressionLevel,
+    /// Smaller responses are sent as is.
+    pub min_size_bytes: u64,
+    /// Response types to compress, e.g. `application/json` or `text/*`.
+    pub content_types: Vec<String>,
+    /// Accept request bodies compressed with one of `encodings`.
+    pub decompress_requests: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: true,
+            encodings: vec![Encoding::Zstd, Encoding::Br, Encoding::Gzip],
+            level: CompressionLevel::default(),
+            min_size_bytes: 1024,
+            content_types: ["application/json", "text/*"].map(String::from).to_vec(),
+            decompress_requests: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Br,
+    Zstd,
+}
+
+impl Encoding {
+    /// The `Content-Encoding` token.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionLevel {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+}
+
+/// HTTP protocol selection and per-connection limits, for every listener.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub protocol: HttpProtocol,
+    /// Connections with no request in flight for this long are closed.
+    pub keep_alive_timeout_seconds: u64,
+    /// How long an HTTP/1 client gets to send a request's headers.
+    pub header_read_timeout_seconds: u64,
+    /// HTTP/2 streams a client may have open at once.
+    pub max_concurrent_streams: u32,
+    /// Largest request head accepted, in bytes; HTTP/1 requests over it
+    /// get 431.
+    pub max_header_bytes: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            protocol: HttpProtocol::default(),
+            keep_alive_timeout_seconds: 75,
+            header_read_timeout_seconds: 30,
+            max_concurrent_streams: 200,
+            max_header_bytes: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpProtocol {
+    /// HTTP/1.1 or HTTP/2: negotiated via ALPN over TLS, told apart by the
+    /// HTTP/2 connection preface in cleartext (h2c with prior knowledge).
+    #[default]
+    Auto,
+    Http1,
+    /// HTTP/2 only, which in cleartext means h2c with prior knowledge.
+    Http2,
+}
+
+/// Headers added to every response that doesn't already set them.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    /// `Strict-Transport-Security` max-age; 0 leaves the header out.
+    /// Browsers ignore it on plain HTTP, so it only takes effect behind TLS.
+    pub hsts_max_age_seconds: u64,
+    pub hsts_include_subdomains: bool,
+    pub frame_options: FrameOptions,
+    pub referrer_policy: String,
+    /// `Content-Security-Policy` for API responses; empty leaves it out.
+    pub content_security_policy: String,
+    /// `Content-Security-Policy` for Swagger UI, which needs its own
+    /// scripts, inline styles and `data:` images; empty leaves it out.
+    pub docs_content_security_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        SecurityHeadersConfig {
+            enabled: true,
+            hsts_max_age_seconds: 31_536_000,
+            hsts_include_subdomains: false,
+            frame_options: FrameOptions::default(),
+            referrer_policy: "no-referrer".to_string(),
+            content_security_policy: "default-src 'none'; frame-ancestors 'none'".to_string(),
+            docs_content_security_policy:
+                "default-src 'self'; img-src 'self' data:; style-src 'self' 'unsafe-inline'; frame-ancestors 'none'"
+                    .to_string(),
+        }
+    }
+}
+
+/// `X-Frame-Options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameOptions {
+    #[default]
+    Deny,
+    SameOrigin,
+}
+
+/// PEM certificate chain and private key. Both files are watched, so a
+/// rotated certificate is served to new connections without a restart.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Also listen for plain HTTP on this port, redirecting every request
+    /// to HTTPS.
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
+    /// Verify client certificates (mutual TLS).
+    #[serde(default)]
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClientAuthConfig {
+    /// PEM bundle of the CAs client certificates must chain to.
+    pub ca_path: PathBuf,
+    #[serde(default)]
+    pub mode: ClientAuthMode,
+    /// PEM CRLs; certificates they revoke are rejected during the handshake.
+    #[serde(default)]
+    pub crl_paths: Vec<PathBuf>,
+    /// Which name in the certificate becomes the principal.
+    #[serde(default)]
+    pub principal_from: CertificateName,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthMode {
+    /// Connections without a certificate are accepted and authenticate by
+    /// other means.
+    Optional,
+    #[default]
+    Required,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateName {
+    /// The subject's common name (CN).
+    #[default]
+    CommonName,
+    /// The first DNS subject alternative name.
+    DnsSan,
+    /// The first URI subject alternative name, e.g. a SPIFFE ID.
+    UriSan,
+    /// The first email subject alternative name.
+    EmailSan,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct LogConfig {
     pub level: String,
+    /// Directives for other targets, in `EnvFilter` syntax. The application's
+    /// own target always uses `level`.
+    #[serde(default = "default_log_filter")]
+    pub filter: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    #[serde(default = "default_log_sinks")]
+    pub sinks: Vec<LogSinkConfig>,
+    #[serde(default)]
+    pub otlp: OtlpConfig,
+    #[serde(default)]
+    pub access: AccessLogConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "INFO".to_string(),
+            filter: default_log_filter(),
+            format: LogFormat::default(),
+            sinks: default_log_sinks(),
+            otlp: OtlpConfig::default(),
+            access: AccessLogConfig::default(),
+        }
+    }
+}
+
+fn default_log_filter() -> String {
+    "tower_http=debug,axum::rejection=trace".to_string()
+}
+
+fn default_log_sinks() -> Vec<LogSinkConfig> {
+    vec![LogSinkConfig {
+        kind: LogSinkKind::Stdout,
+        format: None,
+        path: None,
+        rotation: LogRotation::Never,
+        max_size_bytes: None,
+        max_files: 0,
+    }]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Apache/NCSA Combined Log Format.
+    #[default]
+    Combined,
+    Json,
+}
+
+/// The HTTP access log: one line per request, written to its own sinks and
+/// independent of the application log level.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Where access lines go; the sinks' `format` is ignored.
+    #[serde(default = "default_log_sinks")]
+    pub sinks: Vec<LogSinkConfig>,
+    /// Adds JSON and text request/response bodies to JSON access lines.
+    #[serde(default)]
+    pub capture_bodies: bool,
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Extra field names to redact, in the access line itself (e.g.
+    /// `client_ip`), in query strings and in captured JSON bodies. Known
+    /// secret and PII fields are always redacted.
+    #[serde(default)]
+    pub redact_fields: Vec<String>,
+    /// Peers whose `X-Forwarded-For` names the logged client, as addresses
+    /// or CIDR ranges.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        AccessLogConfig {
+            enabled: false,
+            format: AccessLogFormat::default(),
+            sinks: default_log_sinks(),
+            capture_bodies: false,
+            max_body_bytes: default_max_body_bytes(),
+            redact_fields: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+fn default_max_body_bytes() -> usize {
+    4096
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Full,
+    Pretty,
+    Compact,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSinkKind {
+    Stdout,
+    Stderr,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LogSinkConfig {
+    pub kind: LogSinkKind,
+    /// Overrides `log.format` for this sink.
+    #[serde(default)]
+    pub format: Option<LogFormat>,
+    /// Log file path; required for file sinks.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// Rotates the file once it would grow past this size.
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// Rotated files to keep; 0 keeps all of them.
+    #[serde(default)]
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OtlpConfig {
+    pub enabled: bool,
+    /// OTLP/HTTP traces endpoint, including the `/v1/traces` path.
+    pub endpoint: String,
+    pub service_name: String,
+    /// Fraction of new traces to sample. Incoming `traceparent` sampling
+    /// decisions are always honored.
+    pub sample_ratio: f64,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            enabled: false,
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "hello_cargo".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+/// Either a full `url`, or the parts to assemble one from.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 pub struct DatabaseConfig {
-    pub url: String,
+    #[serde(default)]
+    pub url: Option<Secret<String>>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret<String>>,
+    /// Read each time a connection is opened, so a rotated password is used
+    /// by new pool connections without a restart.
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+    #[serde(default)]
+    pub dbname: Option<String>,
+    /// Apply pending migrations at startup instead of refusing to serve.
+    #[serde(default)]
+    pub auto_migrate: bool,
+    #[serde(default)]
+    pub pool: PoolConfig,
+}
+
+impl DatabaseConfig {
+    /// The connection URL, reading `password_file` if one is set.
+    pub fn connection_url(&self) -> Result<Secret<String>, String> {
+        if let Some(url) = &self.url {
+            return Ok(url.clone());
+        }
+        let host = self.host.as_deref().ok_or("database.host or database.url is required")?;
+        let password = match (&self.password, &self.password_file) {
+            (Some(password), _) => Some(password.expose().clone()),
+            (None, Some(path)) => Some(read_secret(&path.to_string_lossy())?),
+            (None, None) => None,
+        };
+
+        let mut url = "postgres://".to_string();
+        if let Some(user) = &self.user {
+            url.push_str(&percent_encode(user));
+            if let Some(password) = password {
+                url.push(':');
+                url.push_str(&percent_encode(&password));
+            }
+            url.push('@');
+        }
+        url.push_str(host);
+        if let Some(port) = self.port {
+            url.push_str(&format!(":{}", port));
+        }
+        if let Some(dbname) = &self.dbname {
+            url.push('/');
+            url.push_str(&percent_encode(dbname));
+        }
+        Ok(Secret::new(url))
+    }
+}
+
+/// Escapes everything but RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Most connections the pool opens.
+    pub max_size: u32,
+    /// Idle connections kept open; defaults to `max_size`.
+    pub min_idle: Option<u32>,
+    /// How long a request waits for a connection before failing.
+    pub connection_timeout_seconds: u64,
+    /// Idle connections are closed after this long; unset keeps them.
+    pub idle_timeout_seconds: Option<u64>,
+    /// Connections are replaced after this long; unset keeps them.
+    pub max_lifetime_seconds: Option<u64>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            min_idle: None,
+            connection_timeout_seconds: 30,
+            idle_timeout_seconds: Some(600),
+            max_lifetime_seconds: Some(1800),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// Client certificate principals (see `server.tls.client_auth`) granted
+    /// access to the `/admin` endpoints.
+    pub admin_certificates: Vec<String>,
+    /// Keys accepted only by the admin routes, so operator credentials are
+    /// useless against the public API. Each grants admin access whatever
+    /// its `admin` flag says.
+    pub admin_api_keys: Vec<ApiKeyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ApiKeyConfig {
+    /// Principal name the key authenticates as.
+    pub name: String,
+    pub key: Secret<String>,
+    /// Grants access to the `/admin` endpoints.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Tokens added to each client's bucket per second.
+    pub per_second: f64,
+    /// Bucket capacity, i.e. how many requests a client may burst.
+    pub burst: u32,
+    /// Peers allowed to set `X-Forwarded-For`, as addresses or CIDR ranges.
+    pub trusted_proxies: Vec<IpNet>,
+    pub routes: Vec<RouteRateLimitConfig>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            per_second: 10.0,
+            burst: 50,
+            trusted_proxies: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
 }
 
-pub fn load_config() -> Result<AppConfig, config::ConfigError> {
-    let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
-    let run_mode = if run_mode.is_empty() { "development" } else { &run_mode };
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RouteRateLimitConfig {
+    /// Route template as registered on the router, e.g. `/users/:id`.
+    pub path: String,
+    /// Restricts the override to one method; applies to all methods when absent.
+    pub method: Option<String>,
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Cross-origin requests from browsers. Disabled while `allowed_origins` is
+/// empty.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Exact origins (`https://app.example.com`), any subdomain of one
+    /// (`https://*.example.com`), a regex matched against the whole origin
+    /// (`regex:^https://pr-[0-9]+\.example\.com$`), or `*` for any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers a page may send; `*` allows any.
+    pub allowed_headers: Vec<String>,
+    /// Response headers a page may read besides the CORS-safelisted ones.
+    pub expose_headers: Vec<String>,
+    /// Allow cookies and other credentials. Can't be combined with `*`
+    /// origins.
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response.
+    pub max_age_seconds: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: ["content-type", "x-api-key", "x-request-id"].map(String::from).to_vec(),
+            expose_headers: ["x-request-id"].map(String::from).to_vec(),
+            allow_credentials: false,
+            max_age_seconds: 600,
+        }
+    }
+}
 
-    let config = Config::builder()
-        .add_source(File::with_name("config/default"))
-        .add_source(File::with_name(&format!("config/default.{}", run_mode)).required(false))
-        .add_source(File::with_name("config/local").required(false))
-        .add_source(Environment::with_prefix("APP").try_parsing(true).separator("_").list_separator(" "))
-        .build()?;
+/// Who may see Swagger UI and the OpenAPI spec.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DocsConfig {
+    pub mode: DocsMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocsMode {
+    /// Anyone.
+    #[default]
+    Public,
+    /// Callers with an API key or client certificate.
+    Authenticated,
+    /// Nobody; the routes aren't mounted.
+    Disabled,
+}
+
+/// A single invalid setting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigProblem {
+    /// Dotted key path, e.g. `log.sinks[0].path`.
+    pub key: String,
+    /// The layer that set the key, e.g. `config/local` or `APP_* env`.
+    pub source: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (from {}): {}", self.key, self.source, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A layer could not be read or parsed.
+    Load(config::ConfigError),
+    /// Every problem found in the merged configuration.
+    Invalid(Vec<ConfigProblem>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Load(error) => write!(f, "Failed to load configuration: {}", error),
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<config::ConfigError> for ConfigError {
+    fn from(error: config::ConfigError) -> Self {
+        ConfigError::Load(error)
+    }
+}
+
+/// Settings taking a list, given in `APP_*` variables as space-separated
+/// values.
+const LIST_KEYS: &[&str] = &[
+    "server.compression.encodings",
+    "server.compression.content_types",
+    "server.tls.client_auth.crl_paths",
+    "log.access.redact_fields",
+    "log.access.trusted_proxies",
+    "auth.admin_certificates",
+    "rate_limit.trusted_proxies",
+    "cors.allowed_origins",
+    "cors.allowed_methods",
+    "cors.allowed_headers",
+    "cors.expose_headers",
+];
+
+/// The configuration layers, lowest precedence first, kept apart so problems
+/// can be traced back to the layer that set the offending key.
+struct Layers(Vec<(String, Config)>);
+
+impl Layers {
+    /// Reads every layer and resolves `${VAR}` and `*_file` indirection in
+    /// each, returning the problems found along the way.
+    fn load(dir: &Path, run_mode: &str, env: Option<HashMap<String, String>>) -> Result<(Self, Vec<ConfigProblem>), config::ConfigError> {
+        const ENV_LAYER: &str = "APP_* env";
+        let mut problems = Vec::new();
+
+        let mut env = env.unwrap_or_else(|| std::env::vars().collect());
+        for (key, message) in resolve::resolve_env_files(&mut env) {
+            problems.push(ConfigProblem { key, source: ENV_LAYER.to_string(), message });
+        }
+
+        let file = |name: &str| File::from(dir.join(name));
+        // `APP_SERVER_PORT` nests on every `_`; keys with `_` in them need
+        // `__` between the levels instead, as in `APP_RATE_LIMIT__BURST`
+        let (nested, flat): (HashMap<_, _>, HashMap<_, _>) =
+            env.clone().into_iter().partition(|(var, _)| var.strip_prefix("APP_").is_some_and(|name| name.contains("__")));
+        let environment = |separator: &str, vars: HashMap<String, String>| {
+            LIST_KEYS.iter().fold(
+                Environment::with_prefix("APP")
+                    .prefix_separator("_")
+                    .separator(separator)
+                    .try_parsing(true)
+                    .list_separator(" "),
+                |environment, key| environment.with_list_parse_key(key),
+            )
+            .source(Some(vars))
+        };
+        let raw = vec![
+            ("config/default".to_string(), Config::builder().add_source(file("default")).build()?),
+            (
+                format!("config/default.{}", run_mode),
+                Config::builder().add_source(file(&format!("default.{}", run_mode)).required(false)).build()?,
+            ),
+            ("config/local".to_string(), Config::builder().add_source(file("local").required(false)).build()?),
+            (
+                ENV_LAYER.to_string(),
+                Config::builder().add_source(environment("_", flat)).add_source(environment("__", nested)).build()?,
+            ),
+        ];
+
+        let mut layers = Vec::new();
+        for (name, layer) in raw {
+            let mut table = layer.collect()?;
+            for (key, message) in resolve::resolve_table(&mut table, "", &env) {
+                problems.push(ConfigProblem { key, source: name.clone(), message });
+            }
+            layers.push((name, Config::builder().add_source(resolve::Resolved(table)).build()?));
+        }
+        Ok((Layers(layers), problems))
+    }
+
+    fn merged(&self) -> Result<Config, config::ConfigError> {
+        self.0
+            .iter()
+            .fold(Config::builder(), |builder, (_, layer)| builder.add_source(layer.clone()))
+            .build()
+    }
+
+    /// The highest-precedence layer setting exactly `key`.
+    fn setting(&self, key: &str) -> Option<String> {
+        let (name, _) = self.0.iter().rev().find(|(_, layer)| layer.get::<config::Value>(key).is_ok())?;
+        Some(name.clone())
+    }
+
+    /// The highest-precedence layer setting `key`, or its closest parent for
+    /// keys that fell back to a built-in default.
+    fn source_of(&self, key: &str) -> String {
+        let mut key = key;
+        loop {
+            if let Some(name) = self.setting(key) {
+                return name;
+            }
+            match key.rfind(['.', '[']) {
+                Some(end) => key = &key[..end],
+                None => return BUILT_IN_DEFAULT.to_string(),
+            }
+        }
+    }
+}
+
+const BUILT_IN_DEFAULT: &str = "built-in default";
+
+/// The layer each configuration value came from, keyed by paths such as
+/// `server.port` or `auth.api_keys[0].name`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigSources(BTreeMap<String, String>);
+
+/// A configuration value, secrets redacted, and the layer that set it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SourcedValue {
+    pub value: serde_json::Value,
+    pub source: String,
+}
+
+impl ConfigSources {
+    /// Records that `source`, e.g. the command line, set `key`.
+    pub fn set(&mut self, key: &str, source: &str) {
+        self.0.insert(key.to_string(), source.to_string());
+    }
+
+    /// The layer that set `key`, or its closest recorded parent.
+    pub fn source_of(&self, key: &str) -> &str {
+        let mut key = key;
+        loop {
+            if let Some(source) = self.0.get(key) {
+                return source;
+            }
+            match key.rfind(['.', '[']) {
+                Some(end) => key = &key[..end],
+                None => return BUILT_IN_DEFAULT,
+            }
+        }
+    }
+
+    /// Takes the sources of `key` and everything under it from `previous`,
+    /// for settings that keep their running value.
+    pub(crate) fn keep(&mut self, previous: &ConfigSources, key: &str) {
+        let within = |candidate: &str| {
+            candidate.strip_prefix(key).is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        };
+        self.0.retain(|candidate, _| !within(candidate));
+        for (candidate, source) in &previous.0 {
+            if within(candidate) {
+                self.0.insert(candidate.clone(), source.clone());
+            }
+        }
+    }
+
+    /// Every value in `config`, with secrets redacted, and where it came from.
+    pub fn annotate(&self, config: &AppConfig) -> BTreeMap<String, SourcedValue> {
+        flatten(config)
+            .into_iter()
+            .map(|(key, value)| {
+                let source = self.source_of(&key).to_string();
+                (key, SourcedValue { value, source })
+            })
+            .collect()
+    }
+}
+
+/// The leaves of `config` as serialized, so [`Secret`]s come out redacted.
+fn flatten(config: &AppConfig) -> Vec<(String, serde_json::Value)> {
+    fn walk(prefix: String, value: serde_json::Value, leaves: &mut Vec<(String, serde_json::Value)>) {
+        match value {
+            serde_json::Value::Object(table) if !table.is_empty() => {
+                for (key, value) in table {
+                    let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                    walk(key, value, leaves);
+                }
+            }
+            serde_json::Value::Array(values) if !values.is_empty() => {
+                for (index, value) in values.into_iter().enumerate() {
+                    walk(format!("{}[{}]", prefix, index), value, leaves);
+                }
+            }
+            value => leaves.push((prefix, value)),
+        }
+    }
+
+    let mut leaves = Vec::new();
+    // Every field serializes to JSON, so this can't fail
+    walk(String::new(), serde_json::to_value(config).unwrap_or_default(), &mut leaves);
+    leaves
+}
+
+/// Deserializes one top-level section, recording a problem instead of
+/// failing so the other sections are still checked.
+fn section<T: DeserializeOwned>(merged: &Config, layers: &Layers, key: &str, problems: &mut Vec<ConfigProblem>) -> Option<T> {
+    let value = match merged.get::<config::Value>(key) {
+        Ok(value) => value,
+        Err(config::ConfigError::NotFound(_)) => config::Value::new(None, config::ValueKind::Table(Default::default())),
+        Err(error) => {
+            push_problem(problems, ConfigProblem { key: key.to_string(), source: layers.source_of(key), message: error.to_string() });
+            return None;
+        }
+    };
+    match value.try_deserialize() {
+        Ok(section) => Some(section),
+        Err(config::ConfigError::Type { unexpected, expected, key: field, .. }) => {
+            let key = match field {
+                Some(field) => format!("{}.{}", key, field),
+                None => key.to_string(),
+            };
+            let source = layers.source_of(&key);
+            push_problem(problems, ConfigProblem { key, source, message: format!("expected {}, found {}", expected, unexpected) });
+            None
+        }
+        Err(error) => {
+            push_problem(problems, ConfigProblem { key: key.to_string(), source: layers.source_of(key), message: error.to_string() });
+            None
+        }
+    }
+}
+
+/// Records `problem` unless its key already has one, e.g. a value that
+/// failed interpolation and then, left as is, failed to deserialize.
+fn push_problem(problems: &mut Vec<ConfigProblem>, problem: ConfigProblem) {
+    if !problems.iter().any(|existing| existing.key == problem.key) {
+        problems.push(problem);
+    }
+}
+
+/// Loads `default.toml`, `default.{run_mode}.toml` and `local.toml` from
+/// `dir`, then `APP_*` variables from `env` (the process environment when
+/// `None`), and validates the result.
+pub fn load_config_from(dir: &Path, run_mode: &str, env: Option<HashMap<String, String>>) -> Result<AppConfig, ConfigError> {
+    load_config_with_sources(dir, run_mode, env).map(|(config, _)| config)
+}
+
+/// Like [`load_config_from`], also returning the layer each value came from.
+pub fn load_config_with_sources(
+    dir: &Path,
+    run_mode: &str,
+    env: Option<HashMap<String, String>>,
+) -> Result<(AppConfig, ConfigSources), ConfigError> {
+    let (layers, mut problems) = Layers::load(dir, run_mode, env)?;
+    let merged = layers.merged()?;
+
+    let server = section(&merged, &layers, "server", &mut problems);
+    let log = section(&merged, &layers, "log", &mut problems);
+    let database = section(&merged, &layers, "database", &mut problems);
+    let auth = section(&merged, &layers, "auth", &mut problems);
+    let rate_limit = section(&merged, &layers, "rate_limit", &mut problems);
+    let cors = section(&merged, &layers, "cors", &mut problems);
+    let docs = section(&merged, &layers, "docs", &mut problems);
+
+    let config = match (server, log, database, auth, rate_limit, cors, docs) {
+        (Some(server), Some(log), Some(database), Some(auth), Some(rate_limit), Some(cors), Some(docs))
+            if problems.is_empty() =>
+        {
+            AppConfig { server, log, database, auth, rate_limit, cors, docs }
+        }
+        _ => return Err(ConfigError::Invalid(problems)),
+    };
+
+    let problems: Vec<ConfigProblem> = validate::validate(&config)
+        .into_iter()
+        .map(|(key, message)| ConfigProblem { source: layers.source_of(&key), key, message })
+        .collect();
+    if !problems.is_empty() {
+        return Err(ConfigError::Invalid(problems));
+    }
+    let sources = flatten(&config).into_iter().map(|(key, _)| {
+        let source = layers.setting(&key).unwrap_or_else(|| BUILT_IN_DEFAULT.to_string());
+        (key, source)
+    });
+    let sources = ConfigSources(sources.collect());
+    Ok((config, sources))
+}
+
+/// Re-checks `config` after changes made outside the config layers, such
+/// as command-line overrides, attributing every problem to `source`.
+pub fn revalidate(config: &AppConfig, source: &str) -> Result<(), ConfigError> {
+    let problems: Vec<ConfigProblem> = validate::validate(config)
+        .into_iter()
+        .map(|(key, message)| ConfigProblem { key, source: source.to_string(), message })
+        .collect();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+/// `RUN_MODE`, defaulting to `development`.
+pub fn run_mode() -> String {
+    std::env::var("RUN_MODE")
+        .ok()
+        .filter(|run_mode| !run_mode.is_empty())
+        .unwrap_or_else(|| "development".to_string())
+}
 
-    config.try_deserialize()
+pub fn load_config() -> Result<AppConfig, ConfigError> {
+    load_config_from(Path::new("config"), &run_mode(), None)
 }
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use config::{Map, Source, Value, ValueKind};
+
+use super::AppConfig;
+
+/// `_file` keys the application reads itself each time the value is needed,
+/// so a secret rotated on disk is picked up without a reload.
+const LAZY_FILE_KEYS: &[&str] = &["database.password_file"];
+
+const ENV_FILE_SUFFIX: &str = "_FILE";
+
+/// One layer's values after resolution, fed back into the config builder.
+#[derive(Debug, Clone)]
+pub(super) struct Resolved(pub(super) Map<String, Value>);
+
+impl Source for Resolved {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, config::ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// The config key an `APP_*` variable sets: levels are split on `__` when
+/// the name has one, else on every `_`.
+fn env_key(var: &str) -> Option<String> {
+    let name = var.strip_prefix("APP_")?;
+    let separator = if name.contains("__") { "__" } else { "_" };
+    Some(name.split(separator).collect::<Vec<_>>().join(".").to_lowercase())
+}
+
+/// Whether `key` is a setting of [`AppConfig`]. Keys under an optional
+/// setting that is unset by default are taken on trust unless `exact`.
+fn is_known_key(key: &str, exact: bool) -> bool {
+    let Ok(mut value) = serde_json::to_value(AppConfig::default()) else {
+        return false;
+    };
+    for segment in key.split('.') {
+        value = match value {
+            serde_json::Value::Object(mut fields) => match fields.remove(segment) {
+                Some(field) => field,
+                None => return false,
+            },
+            serde_json::Value::Null => return !exact,
+            _ => return false,
+        };
+    }
+    true
+}
+
+/// Reads the file named by each `APP_*_FILE` variable for a known key into
+/// `APP_*`, returning `(key, message)` for every one that can't be used.
+/// Other `_FILE` variables are left alone, unread.
+pub(super) fn resolve_env_files(env: &mut HashMap<String, String>) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let file_vars: Vec<(String, String)> = env
+        .keys()
+        .filter(|name| name.ends_with(ENV_FILE_SUFFIX))
+        // A key of its own, like `database.password_file`, is read later
+        .filter(|name| !env_key(name).is_some_and(|key| is_known_key(&key, true)))
+        .filter_map(|name| {
+            let key = env_key(name.trim_end_matches(ENV_FILE_SUFFIX))?;
+            is_known_key(&key, false).then(|| (name.clone(), key))
+        })
+        .collect();
+    for (file_var, key) in file_vars {
+        let path = env.remove(&file_var).unwrap_or_default();
+        let var = file_var.trim_end_matches(ENV_FILE_SUFFIX).to_string();
+        let file_key = format!("{}_file", key);
+        if env.contains_key(&var) {
+            problems.push((file_key, format!("conflicts with {}; set only one of them", var)));
+            continue;
+        }
+        match read_secret(&path) {
+            Ok(contents) => {
+                env.insert(var, contents);
+            }
+            Err(message) => problems.push((file_key, message)),
+        }
+    }
+    problems
+}
+
+/// Applies `${VAR}` interpolation to every string in `table` and replaces
+/// each `key_file = "path"` with `key` set to the file's contents. Returns
+/// `(key, message)` for every value that can't be resolved.
+pub(super) fn resolve_table(table: &mut Map<String, Value>, prefix: &str, env: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let keys: Vec<String> = table.keys().cloned().collect();
+    for key in keys {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        let Some(value) = table.get_mut(&key) else {
+            continue;
+        };
+        problems.extend(resolve_value(value, &path, env));
+
+        let Some(name) = key.strip_suffix("_file") else {
+            continue;
+        };
+        if LAZY_FILE_KEYS.contains(&path.as_str()) {
+            continue;
+        }
+        if let ValueKind::String(file) = &value.kind {
+            match read_secret(file) {
+                Ok(contents) => {
+                    let resolved = Value::new(value.origin().map(str::to_owned).as_ref(), contents);
+                    table.remove(&key);
+                    table.insert(name.to_string(), resolved);
+                }
+                Err(message) => problems.push((path, message)),
+            }
+        }
+    }
+    problems
+}
+
+fn resolve_value(value: &mut Value, path: &str, env: &HashMap<String, String>) -> Vec<(String, String)> {
+    match &mut value.kind {
+        ValueKind::String(string) => match interpolate(string, env) {
+            Ok(interpolated) => {
+                *string = interpolated;
+                Vec::new()
+            }
+            Err(message) => vec![(path.to_string(), message)],
+        },
+        ValueKind::Table(table) => resolve_table(table, path, env),
+        ValueKind::Array(values) => values
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(index, value)| resolve_value(value, &format!("{}[{}]", path, index), env))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Expands `${VAR}` and `${VAR:-default}`; `$${` is a literal `${`.
+fn interpolate(value: &str, env: &HashMap<String, String>) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix("${") {
+            interpolated.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(body) = after.strip_prefix('{') else {
+            interpolated.push('$');
+            rest = after;
+            continue;
+        };
+        let end = body.find('}').ok_or_else(|| "has an unterminated ${".to_string())?;
+        let (name, default) = match body[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&body[..end], None),
+        };
+        match env.get(name).map(String::as_str).or(default) {
+            Some(value) => interpolated.push_str(value),
+            None => return Err(format!("refers to environment variable {}, which is not set", name)),
+        }
+        rest = &body[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Reads a secret file, dropping the trailing newline most tools add.
+pub(crate) fn read_secret(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path)
+        .map(|contents| contents.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|error| format!("cannot read {}: {}", path, error))
+}
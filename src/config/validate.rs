@@ -0,0 +1,271 @@
+use std::collections::HashSet;
+use axum::http::{HeaderName, HeaderValue, Method};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+use super::{read_secret, AppConfig, LogSinkConfig, LogSinkKind};
+use crate::cors::OriginPattern;
+use crate::ROUTES;
+
+const UNKNOWN_ROUTE: &str = "is not a route of this server; use its template, e.g. /users/:id";
+
+/// Checks the settings serde can't, returning `(key, message)` for every
+/// problem found.
+pub(super) fn validate(config: &AppConfig) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let mut problem = |key: &str, message: &str| problems.push((key.to_string(), message.to_string()));
+
+    let server = &config.server;
+    if server.request_timeout_seconds == 0 {
+        problem("server.request_timeout_seconds", "must be greater than 0");
+    }
+    if server.body_limit_bytes == 0 {
+        problem("server.body_limit_bytes", "must be greater than 0");
+    }
+    if server.max_concurrent_requests == 0 {
+        problem("server.max_concurrent_requests", "must be greater than 0");
+    }
+    for (index, route) in server.routes.iter().enumerate() {
+        let key = |field: &str| format!("server.routes[{}].{}", index, field);
+        if !ROUTES.contains(&route.path.as_str()) {
+            problem(&key("path"), UNKNOWN_ROUTE);
+        }
+        if route.method.as_ref().is_some_and(|method| method.parse::<Method>().is_err()) {
+            problem(&key("method"), "is not a valid HTTP method");
+        }
+        if route.body_limit_bytes.is_none() && route.request_timeout_seconds.is_none() {
+            problem(&key("path"), "sets neither body_limit_bytes nor request_timeout_seconds");
+        }
+        if route.body_limit_bytes == Some(0) {
+            problem(&key("body_limit_bytes"), "must be greater than 0");
+        }
+        if route.request_timeout_seconds == Some(0) {
+            problem(&key("request_timeout_seconds"), "must be greater than 0");
+        }
+    }
+    if let Some(tls) = &server.tls {
+        if let Err(message) = crate::tls::server_config(tls, server.http.protocol) {
+            problem("server.tls", &message);
+        }
+        if tls.redirect_port == Some(server.port) {
+            problem("server.tls.redirect_port", "must differ from server.port");
+        }
+        if !server.listeners.is_empty() && !server.listeners.iter().any(|listener| listener.tls) {
+            problem("server.tls", "is configured but no listener has tls = true");
+            if tls.redirect_port.is_some() {
+                problem("server.tls.redirect_port", "needs a listener with tls = true to redirect to");
+            }
+        }
+    }
+    for (index, listener) in server.listeners.iter().enumerate() {
+        let key = |field: &str| format!("server.listeners[{}].{}", index, field);
+        match (&listener.tcp, &listener.unix, &listener.systemd) {
+            (Some(_), Some(_), _) => problem(&key("unix"), "conflicts with tcp; set only one of them"),
+            (Some(_), None, Some(_)) | (None, Some(_), Some(_)) => {
+                problem(&key("systemd"), "conflicts with tcp and unix; set only one of them")
+            }
+            (None, None, None) => problem(&key("tcp"), "or unix or systemd is required"),
+            _ => {}
+        }
+        if let Some(mode) = &listener.mode {
+            if listener.systemd.is_some() {
+                problem(&key("mode"), "doesn't apply to sockets passed by systemd; set SocketMode= in the socket unit");
+            } else if listener.unix.is_none() {
+                problem(&key("mode"), "only applies to unix listeners");
+            } else if u32::from_str_radix(mode, 8).map_or(true, |mode| mode > 0o777) {
+                problem(&key("mode"), "must be octal permissions such as \"660\"");
+            }
+        }
+        if listener.trust_forwarded && listener.tcp.is_some() {
+            problem(&key("trust_forwarded"), "only applies to unix listeners; use rate_limit.trusted_proxies");
+        }
+        if listener.tls && listener.unix.is_some() {
+            problem(&key("tls"), "is only supported on tcp listeners");
+        } else if listener.tls && server.tls.is_none() {
+            problem(&key("tls"), "requires server.tls");
+        }
+    }
+
+    let log = &config.log;
+    if log.level.parse::<Level>().is_err() {
+        problem("log.level", "must be one of TRACE, DEBUG, INFO, WARN or ERROR");
+    }
+    if !log.filter.is_empty() && EnvFilter::try_new(&log.filter).is_err() {
+        problem("log.filter", "is not a valid filter directive list");
+    }
+    validate_sinks("log.sinks", &log.sinks, &mut problem);
+    if !(0.0..=1.0).contains(&log.otlp.sample_ratio) {
+        problem("log.otlp.sample_ratio", "must be between 0.0 and 1.0");
+    }
+    if log.otlp.enabled && log.otlp.endpoint.is_empty() {
+        problem("log.otlp.endpoint", "is required when OTLP export is enabled");
+    }
+    if log.access.enabled {
+        validate_sinks("log.access.sinks", &log.access.sinks, &mut problem);
+        if log.access.capture_bodies && log.access.max_body_bytes == 0 {
+            problem("log.access.max_body_bytes", "must be greater than 0 when capturing bodies");
+        }
+    }
+
+    let database = &config.database;
+    match (&database.url, &database.host) {
+        (Some(url), None) => {
+            if !url.expose().starts_with("postgres://") && !url.expose().starts_with("postgresql://") {
+                problem("database.url", "must be a postgres:// or postgresql:// URL");
+            }
+        }
+        (Some(_), Some(_)) => problem("database.host", "conflicts with database.url; set only one of them"),
+        (None, None) => problem("database.url", "is required unless database.host is set"),
+        (None, Some(_)) => {}
+    }
+    if database.password.is_some() && database.password_file.is_some() {
+        problem("database.password_file", "conflicts with database.password; set only one of them");
+    }
+    if let Some(path) = &database.password_file {
+        if let Err(message) = read_secret(&path.to_string_lossy()) {
+            problem("database.password_file", &message);
+        }
+    }
+    let pool = &database.pool;
+    if pool.max_size == 0 {
+        problem("database.pool.max_size", "must be greater than 0");
+    }
+    if pool.min_idle.is_some_and(|min_idle| min_idle > pool.max_size) {
+        problem("database.pool.min_idle", "must not exceed database.pool.max_size");
+    }
+    if pool.connection_timeout_seconds == 0 {
+        problem("database.pool.connection_timeout_seconds", "must be greater than 0");
+    }
+
+    let mut names = HashSet::new();
+    let mut keys = HashSet::new();
+    let api_keys = config.auth.api_keys.iter().enumerate().map(|(index, api_key)| (format!("auth.api_keys[{}]", index), api_key));
+    let admin_api_keys = config.auth.admin_api_keys.iter().enumerate().map(|(index, api_key)| (format!("auth.admin_api_keys[{}]", index), api_key));
+    for (key, api_key) in api_keys.chain(admin_api_keys) {
+        if api_key.name.is_empty() {
+            problem(&format!("{}.name", key), "must not be empty");
+        } else if !names.insert(&api_key.name) {
+            problem(&format!("{}.name", key), "is used by another API key");
+        }
+        if api_key.key.expose().is_empty() {
+            problem(&format!("{}.key", key), "must not be empty");
+        } else if !keys.insert(api_key.key.expose()) {
+            problem(&format!("{}.key", key), "is used by another API key");
+        }
+    }
+
+    let rate_limit = &config.rate_limit;
+    if rate_limit.enabled {
+        if rate_limit.per_second <= 0.0 {
+            problem("rate_limit.per_second", "must be greater than 0");
+        }
+        if rate_limit.burst == 0 {
+            problem("rate_limit.burst", "must be greater than 0");
+        }
+    }
+    for (index, route) in rate_limit.routes.iter().enumerate() {
+        if !ROUTES.contains(&route.path.as_str()) {
+            problem(&format!("rate_limit.routes[{}].path", index), UNKNOWN_ROUTE);
+        }
+        if route.method.as_ref().is_some_and(|method| method.parse::<Method>().is_err()) {
+            problem(&format!("rate_limit.routes[{}].method", index), "is not a valid HTTP method");
+        }
+        if route.per_second <= 0.0 {
+            problem(&format!("rate_limit.routes[{}].per_second", index), "must be greater than 0");
+        }
+        if route.burst == 0 {
+            problem(&format!("rate_limit.routes[{}].burst", index), "must be greater than 0");
+        }
+    }
+
+    let compression = &config.server.compression;
+    if (compression.enabled || compression.decompress_requests) && compression.encodings.is_empty() {
+        problem("server.compression.encodings", "must list at least one encoding while compression is enabled");
+    }
+    for (index, content_type) in compression.content_types.iter().enumerate() {
+        let valid = content_type
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| !kind.is_empty() && kind != "*" && !subtype.is_empty() && !content_type.contains(';'));
+        if !valid {
+            problem(
+                &format!("server.compression.content_types[{}]", index),
+                "must be a media type such as application/json or text/*",
+            );
+        }
+    }
+
+    let http = &server.http;
+    for (key, value) in [
+        ("server.http.keep_alive_timeout_seconds", http.keep_alive_timeout_seconds),
+        ("server.http.header_read_timeout_seconds", http.header_read_timeout_seconds),
+        ("server.http.max_concurrent_streams", u64::from(http.max_concurrent_streams)),
+    ] {
+        if value == 0 {
+            problem(key, "must be greater than 0");
+        }
+    }
+    // Below this hyper can't buffer a request line and headers at all
+    if http.max_header_bytes < 8192 {
+        problem("server.http.max_header_bytes", "must be at least 8192");
+    }
+
+    let headers = &config.server.security_headers;
+    const REFERRER_POLICIES: [&str; 8] = [
+        "no-referrer",
+        "no-referrer-when-downgrade",
+        "origin",
+        "origin-when-cross-origin",
+        "same-origin",
+        "strict-origin",
+        "strict-origin-when-cross-origin",
+        "unsafe-url",
+    ];
+    if !REFERRER_POLICIES.contains(&headers.referrer_policy.as_str()) {
+        problem("server.security_headers.referrer_policy", &format!("must be one of {}", REFERRER_POLICIES.join(", ")));
+    }
+    for (key, policy) in [
+        ("server.security_headers.content_security_policy", &headers.content_security_policy),
+        ("server.security_headers.docs_content_security_policy", &headers.docs_content_security_policy),
+    ] {
+        if HeaderValue::from_str(policy).is_err() {
+            problem(key, "is not a valid header value");
+        }
+    }
+
+    let cors = &config.cors;
+    for (index, origin) in cors.allowed_origins.iter().enumerate() {
+        let key = format!("cors.allowed_origins[{}]", index);
+        match OriginPattern::parse(origin) {
+            Ok(OriginPattern::Any) if cors.allow_credentials => {
+                problem(&key, "cannot be '*' while cors.allow_credentials is set")
+            }
+            Ok(_) => {}
+            Err(message) => problem(&key, &message),
+        }
+    }
+    for (index, method) in cors.allowed_methods.iter().enumerate() {
+        if method.parse::<Method>().is_err() {
+            problem(&format!("cors.allowed_methods[{}]", index), "is not a valid HTTP method");
+        }
+    }
+    for (key, headers) in [("cors.allowed_headers", &cors.allowed_headers), ("cors.expose_headers", &cors.expose_headers)] {
+        for (index, header) in headers.iter().enumerate() {
+            if header != "*" && header.parse::<HeaderName>().is_err() {
+                problem(&format!("{}[{}]", key, index), "is not a valid header name");
+            }
+        }
+    }
+
+    problems
+}
+
+fn validate_sinks(key: &str, sinks: &[LogSinkConfig], problem: &mut impl FnMut(&str, &str)) {
+    for (index, sink) in sinks.iter().enumerate() {
+        if sink.kind == LogSinkKind::File && sink.path.is_none() {
+            problem(&format!("{}[{}].path", key, index), "is required for file sinks");
+        }
+        if sink.max_size_bytes == Some(0) {
+            problem(&format!("{}[{}].max_size_bytes", key, index), "must be greater than 0");
+        }
+    }
+}
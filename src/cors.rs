@@ -0,0 +1,253 @@
+use std::sync::{Arc, RwLock};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use regex::Regex;
+use serde_json::json;
+use tracing::error;
+
+use crate::config::CorsConfig;
+
+/// One entry of `cors.allowed_origins`.
+#[derive(Debug, Clone)]
+pub enum OriginPattern {
+    Any,
+    Exact(String),
+    /// One or more subdomain labels between `scheme` (with its `://`) and
+    /// `suffix` (with its leading `.` and any port).
+    Subdomain { scheme: String, suffix: String },
+    /// Anchored at both ends.
+    Regex(Regex),
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        if pattern == "*" {
+            return Ok(OriginPattern::Any);
+        }
+        if let Some(regex) = pattern.strip_prefix("regex:") {
+            return Regex::new(&format!("^(?:{})$", regex))
+                .map(OriginPattern::Regex)
+                .map_err(|error| format!("is not a valid regex: {}", error));
+        }
+        let pattern = pattern.to_ascii_lowercase();
+        let host = match pattern.split_once("://") {
+            Some((scheme, host)) if !scheme.is_empty() && !host.is_empty() && !host.contains('/') => host,
+            _ => return Err("must be an origin such as https://app.example.com, without a path".to_string()),
+        };
+        if let Some(suffix) = host.strip_prefix("*.") {
+            if !suffix.is_empty() && !suffix.contains('*') {
+                let scheme = pattern[..pattern.len() - host.len()].to_string();
+                return Ok(OriginPattern::Subdomain { scheme, suffix: format!(".{}", suffix) });
+            }
+        }
+        if host.contains('*') {
+            return Err("may only use * as the leftmost label, e.g. https://*.example.com".to_string());
+        }
+        Ok(OriginPattern::Exact(pattern))
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginPattern::Any => true,
+            OriginPattern::Exact(exact) => origin.eq_ignore_ascii_case(exact),
+            OriginPattern::Subdomain { scheme, suffix } => {
+                let origin = origin.to_ascii_lowercase();
+                let subdomain = origin.strip_prefix(scheme.as_str()).and_then(|host| host.strip_suffix(suffix.as_str()));
+                subdomain.is_some_and(|subdomain| {
+                    subdomain
+                        .split('.')
+                        .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+                })
+            }
+            OriginPattern::Regex(regex) => regex.is_match(origin),
+        }
+    }
+}
+
+fn header_list(values: &[String]) -> Option<HeaderValue> {
+    if values.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&values.join(", ")).ok()
+}
+
+/// `CorsConfig` parsed into what the middleware checks against.
+struct Policy {
+    origins: Vec<OriginPattern>,
+    methods: Vec<Method>,
+    allow_methods: Option<HeaderValue>,
+    /// Lowercased.
+    headers: Vec<String>,
+    any_header: bool,
+    expose_headers: Option<HeaderValue>,
+    credentials: bool,
+    max_age: HeaderValue,
+}
+
+impl Policy {
+    fn new(config: &CorsConfig) -> Self {
+        // Validation rejects bad entries; an unvalidated config just loses them
+        let origins = config
+            .allowed_origins
+            .iter()
+            .filter_map(|origin| match OriginPattern::parse(origin) {
+                Ok(pattern) => Some(pattern),
+                Err(message) => {
+                    error!(origin = %origin, "Ignoring CORS origin that {}", message);
+                    None
+                }
+            })
+            .collect();
+        let methods: Vec<Method> = config.allowed_methods.iter().filter_map(|method| method.parse().ok()).collect();
+        let headers: Vec<String> = config.allowed_headers.iter().map(|header| header.to_ascii_lowercase()).collect();
+        Policy {
+            origins,
+            allow_methods: header_list(&methods.iter().map(ToString::to_string).collect::<Vec<_>>()),
+            methods,
+            any_header: headers.iter().any(|header| header == "*"),
+            headers,
+            expose_headers: header_list(&config.expose_headers),
+            credentials: config.allow_credentials,
+            max_age: HeaderValue::from(config.max_age_seconds),
+        }
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        origin.to_str().is_ok_and(|origin| self.origins.iter().any(|pattern| pattern.matches(origin)))
+    }
+
+    /// Whether responses depend on the request's `Origin`, rather than
+    /// allowing every origin with `*`.
+    fn echoes_origin(&self) -> bool {
+        let any = self.origins.iter().any(|pattern| matches!(pattern, OriginPattern::Any));
+        !any || self.credentials
+    }
+
+    /// Marks a response as depending on the request's `Origin`, so caches
+    /// don't serve one origin's response to another, or to a request
+    /// without one.
+    fn vary(&self, headers: &mut HeaderMap) {
+        if self.echoes_origin() {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+    }
+
+    /// Sets the headers every response to an allowed origin carries.
+    fn allow_origin(&self, headers: &mut HeaderMap, origin: HeaderValue) {
+        if self.echoes_origin() {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        }
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+
+    fn preflight(&self, origin: HeaderValue, request: &HeaderMap) -> Response {
+        let method = request
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| method.to_str().ok())
+            .and_then(|method| method.parse::<Method>().ok());
+        let requested_headers: Vec<String> = request
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|header| header.trim().to_ascii_lowercase())
+            .filter(|header| !header.is_empty())
+            .collect();
+
+        let rejected = if !self.allows(&origin) {
+            Some("Origin not allowed")
+        } else if !method.is_some_and(|method| self.methods.contains(&method)) {
+            Some("Method not allowed")
+        } else if !self.any_header && !requested_headers.iter().all(|header| self.headers.contains(header)) {
+            Some("Header not allowed")
+        } else {
+            None
+        };
+        let mut response = match rejected {
+            Some(reason) => {
+                (StatusCode::FORBIDDEN, Json(json!({"error": format!("CORS preflight rejected: {}", reason)}))).into_response()
+            }
+            None => StatusCode::NO_CONTENT.into_response(),
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(
+            header::VARY,
+            HeaderValue::from_static("origin, access-control-request-method, access-control-request-headers"),
+        );
+        if rejected.is_some() {
+            return response;
+        }
+        self.allow_origin(headers, origin);
+        if let Some(methods) = &self.allow_methods {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods.clone());
+        }
+        let allow_headers = if self.any_header { header_list(&requested_headers) } else { header_list(&self.headers) };
+        if let Some(allow_headers) = allow_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, self.max_age.clone());
+        response
+    }
+}
+
+/// The CORS policy in effect, swapped out when the configuration reloads.
+pub struct Cors {
+    policy: RwLock<Arc<Policy>>,
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Self {
+        Cors { policy: RwLock::new(Arc::new(Policy::new(&config))) }
+    }
+
+    /// Swaps in a new policy for requests not yet started.
+    pub fn update(&self, config: CorsConfig) {
+        *self.policy.write().unwrap() = Arc::new(Policy::new(&config));
+    }
+
+    fn policy(&self) -> Arc<Policy> {
+        self.policy.read().unwrap().clone()
+    }
+}
+
+/// Answers preflight requests and adds CORS headers to responses for
+/// allowed origins. Must run outside authentication and rate limiting,
+/// since browsers send preflights without credentials. With CORS disabled,
+/// requests pass through untouched; requests without an `Origin` header
+/// only gain `Vary: origin`.
+pub async fn cors(State(cors): State<Arc<Cors>>, request: Request, next: Next) -> Response {
+    let policy = cors.policy();
+    if policy.origins.is_empty() {
+        return next.run(request).await;
+    }
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        let mut response = next.run(request).await;
+        policy.vary(response.headers_mut());
+        return response;
+    };
+    if request.method() == Method::OPTIONS && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) {
+        return policy.preflight(origin, request.headers());
+    }
+
+    let allowed = policy.allows(&origin);
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    policy.vary(headers);
+    if allowed {
+        policy.allow_origin(headers, origin);
+        if let Some(expose_headers) = &policy.expose_headers {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose_headers.clone());
+        }
+    }
+    response
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+
+use crate::repositories::UserRepositoryArc;
+use crate::server::Shutdown;
+
+pub struct HealthState {
+    user_repository: UserRepositoryArc,
+    shutdown: Shutdown,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "Process is running")
+    )
+)]
+pub async fn live() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!({"status": "ok"})))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "All dependencies are healthy"),
+        (status = 503, description = "At least one dependency is unhealthy, or the server is shutting down")
+    )
+)]
+pub async fn ready(State(state): State<Arc<HealthState>>) -> impl IntoResponse {
+    // Fail first, so load balancers stop routing here while requests drain
+    if state.shutdown.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"status": "draining"})));
+    }
+    let database = state.user_repository.health().await;
+    let (status, label) = if database.healthy {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+    (status, Json(json!({"status": label, "checks": {"database": database}})))
+}
+
+/// Probe routes. These are merged outside the authentication and rate
+/// limiting layers so orchestrators can always reach them.
+pub fn router(user_repository: UserRepositoryArc, shutdown: Shutdown) -> Router {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+        .with_state(Arc::new(HealthState { user_repository, shutdown }))
+}
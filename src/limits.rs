@@ -0,0 +1,159 @@
+use std::any::Any;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http_body::{Frame, SizeHint};
+use serde_json::json;
+use tokio::sync::Semaphore;
+use tower_http::catch_panic::CatchPanicLayer;
+use tracing::error;
+
+use crate::config::{RouteLimitConfig, ServerConfig};
+
+/// Body size and time limits, with per-route overrides.
+pub struct Limits {
+    body_limit_bytes: usize,
+    timeout: Duration,
+    routes: Vec<RouteLimitConfig>,
+}
+
+impl Limits {
+    pub fn new(config: &ServerConfig) -> Self {
+        Limits {
+            body_limit_bytes: config.body_limit_bytes,
+            timeout: Duration::from_secs(config.request_timeout_seconds),
+            routes: config.routes.clone(),
+        }
+    }
+
+    fn for_route(&self, method: &Method, route: Option<&str>) -> (usize, Duration) {
+        let route_override = route.and_then(|route| {
+            self.routes.iter().find(|r| {
+                r.path == route && r.method.as_deref().is_none_or(|m| m.eq_ignore_ascii_case(method.as_str()))
+            })
+        });
+        let body_limit_bytes = route_override.and_then(|r| r.body_limit_bytes).unwrap_or(self.body_limit_bytes);
+        let timeout = route_override
+            .and_then(|r| r.request_timeout_seconds)
+            .map_or(self.timeout, Duration::from_secs);
+        (body_limit_bytes, timeout)
+    }
+}
+
+/// What became of a request body while it was read.
+#[derive(Default)]
+struct BodyProgress {
+    /// The handler is waiting on the client for more of the body.
+    waiting: AtomicBool,
+    exceeded: AtomicBool,
+}
+
+/// Fails the request body once more than `limit` bytes have been read.
+struct LimitedBody {
+    inner: Body,
+    limit: usize,
+    read: usize,
+    progress: Arc<BodyProgress>,
+}
+
+impl HttpBody for LimitedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        self.progress.waiting.store(frame.is_pending(), Ordering::Relaxed);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            self.read += frame.data_ref().map_or(0, Bytes::len);
+            if self.read > self.limit {
+                self.progress.exceeded.store(true, Ordering::Relaxed);
+                return Poll::Ready(Some(Err(axum::Error::new("request body too large"))));
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+fn payload_too_large() -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, Json(json!({"error": "Request body too large"}))).into_response()
+}
+
+/// Applies the route's body limit and timeout. Bodies are counted as read,
+/// so the limit holds for decompressed and chunked bodies too. A timeout
+/// is answered with 408 while the handler waits on the body, and with 504
+/// otherwise.
+pub async fn limit(State(limits): State<Arc<Limits>>, request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned());
+    let (body_limit_bytes, timeout) = limits.for_route(request.method(), route.as_deref());
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > body_limit_bytes as u64) {
+        return payload_too_large();
+    }
+
+    let (parts, body) = request.into_parts();
+    let progress = Arc::new(BodyProgress::default());
+    let body = LimitedBody { inner: body, limit: body_limit_bytes, read: 0, progress: progress.clone() };
+    let request = Request::from_parts(parts, Body::new(body));
+
+    let response = tokio::time::timeout(timeout, next.run(request)).await;
+    if progress.exceeded.load(Ordering::Relaxed) {
+        return payload_too_large();
+    }
+    match response {
+        Ok(response) => response,
+        Err(_) if progress.waiting.load(Ordering::Relaxed) => {
+            (StatusCode::REQUEST_TIMEOUT, Json(json!({"error": "Request body not received in time"}))).into_response()
+        }
+        Err(_) => (StatusCode::GATEWAY_TIMEOUT, Json(json!({"error": "Request timed out"}))).into_response(),
+    }
+}
+
+/// Sheds requests with 503 while every permit is taken, rather than
+/// queueing them behind work the server can't keep up with.
+pub async fn shed_load(State(permits): State<Arc<Semaphore>>, request: Request, next: Next) -> Response {
+    let Ok(_permit) = permits.try_acquire() else {
+        let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Server is overloaded"}))).into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        return response;
+    };
+    next.run(request).await
+}
+
+fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic payload");
+    error!(panic = %message, "Request handler panicked");
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Internal server error"}))).into_response()
+}
+
+/// Answers requests whose handler panicked with 500, instead of dropping
+/// the connection.
+pub fn catch_panic() -> CatchPanicLayer<fn(Box<dyn Any + Send + 'static>) -> Response> {
+    CatchPanicLayer::custom(panic_response as fn(Box<dyn Any + Send + 'static>) -> Response)
+}
@@ -1,15 +1,291 @@
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use serde::Serialize;
+use tracing::{warn, Level, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::{DefaultFields, JsonFields, PrettyFields}, writer::BoxMakeWriter, MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
 
-pub fn setup_logging(log_level: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| {
-            EnvFilter::new(format!("hello_cargo={},tower_http=debug,axum::rejection=trace", log_level))
+use crate::config::{LogConfig, LogFormat, LogSinkConfig, LogSinkKind};
+use crate::telemetry;
+
+pub mod json;
+pub mod redact;
+pub mod rotation;
+
+use json::FlatJson;
+use redact::RedactingFields;
+use rotation::RotatingFile;
+
+/// Keeps the OpenTelemetry pipeline alive; dropping it flushes pending spans.
+pub struct LoggingGuard {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+static LOG_LEVEL_CONTROLLER: OnceLock<LogLevelController> = OnceLock::new();
+
+/// Longest a temporary log filter may stay before reverting.
+pub const MAX_TEMPORARY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The controller for the filter installed by [`setup_logging`], if logging
+/// has been set up in this process.
+pub fn log_level_controller() -> Option<&'static LogLevelController> {
+    LOG_LEVEL_CONTROLLER.get()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLevelState {
+    /// Directives currently in effect.
+    pub directives: String,
+    /// Directives restored when a temporary override expires.
+    pub default_directives: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_in_seconds: Option<u64>,
+}
+
+struct Directives {
+    current: String,
+    default: String,
+    revert_at: Option<Instant>,
+}
+
+/// Swaps the process-wide log filter at runtime.
+pub struct LogLevelController {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: Mutex<Directives>,
+    // Bumped on every change so a stale revert cannot undo a newer one.
+    generation: AtomicU64,
+}
+
+impl LogLevelController {
+    fn new(handle: reload::Handle<EnvFilter, Registry>, directives: String) -> Self {
+        LogLevelController {
+            handle,
+            directives: Mutex::new(Directives { current: directives.clone(), default: directives, revert_at: None }),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn state(&self) -> LogLevelState {
+        let directives = self.directives.lock().unwrap();
+        LogLevelState {
+            directives: directives.current.clone(),
+            default_directives: directives.default.clone(),
+            revert_in_seconds: directives
+                .revert_at
+                .map(|at| at.saturating_duration_since(Instant::now()).as_secs()),
+        }
+    }
+
+    /// Swaps in `new`, writing the `audit` record under whichever of the
+    /// old and new filters lets it through, so turning logging down still
+    /// records who turned it down.
+    fn apply(&self, new: &str, audit: impl FnOnce()) -> Result<(), String> {
+        let filter = EnvFilter::try_new(new).map_err(|e| format!("Invalid log directives: {}", e))?;
+        let audited_before = tracing::enabled!(Level::WARN);
+        if audited_before {
+            audit();
+            self.handle.reload(filter).map_err(|e| e.to_string())
+        } else {
+            self.handle.reload(filter).map_err(|e| e.to_string())?;
+            audit();
+            Ok(())
+        }
+    }
+
+    /// Replaces the filter until the next change.
+    pub fn set(&self, new: &str) -> Result<LogLevelState, String> {
+        let previous = self.state().directives;
+        // Logged at WARN so the record survives all but the most restrictive filters
+        self.apply(new, || warn!(previous = %previous, directives = %new, "Log filter changed"))?;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        {
+            let mut directives = self.directives.lock().unwrap();
+            directives.default = new.to_string();
+            directives.revert_at = None;
+            directives.current = new.to_string();
+        }
+        Ok(self.state())
+    }
+
+    /// Makes `new` the default directives, as on a config reload. An active
+    /// temporary filter stays until its TTL runs out, then reverts to `new`.
+    pub fn set_default(&self, new: &str) -> Result<LogLevelState, String> {
+        let overridden = {
+            let mut directives = self.directives.lock().unwrap();
+            if directives.revert_at.is_some() {
+                EnvFilter::try_new(new).map_err(|e| format!("Invalid log directives: {}", e))?;
+                directives.default = new.to_string();
+            }
+            directives.revert_at.is_some()
+        };
+        if !overridden {
+            return self.set(new);
+        }
+        warn!(directives = %new, "Log filter default changed; the temporary filter stays until it expires");
+        Ok(self.state())
+    }
+
+    /// Replaces the filter for `ttl`, at most [`MAX_TEMPORARY_TTL`], then
+    /// restores the default directives. Must be called from within a Tokio
+    /// runtime.
+    pub fn set_temporarily(&'static self, new: &str, ttl: Duration) -> Result<LogLevelState, String> {
+        // Checked before anything changes, so a bad TTL can't strand the filter
+        let revert_at = Some(ttl)
+            .filter(|ttl| *ttl <= MAX_TEMPORARY_TTL)
+            .and_then(|ttl| Instant::now().checked_add(ttl))
+            .ok_or_else(|| format!("ttl_seconds must be at most {}", MAX_TEMPORARY_TTL.as_secs()))?;
+        let previous = self.state().directives;
+        self.apply(new, || {
+            warn!(previous = %previous, directives = %new, ttl_seconds = ttl.as_secs(), "Log filter temporarily changed")
+        })?;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        {
+            let mut directives = self.directives.lock().unwrap();
+            directives.revert_at = Some(revert_at);
+            directives.current = new.to_string();
+        }
+
+        tokio::spawn(async move {
+            tokio::time::sleep(ttl).await;
+            self.revert(generation);
         });
+        Ok(self.state())
+    }
+
+    fn revert(&self, generation: u64) {
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let (expired, default) = {
+            let mut directives = self.directives.lock().unwrap();
+            directives.revert_at = None;
+            let default = directives.default.clone();
+            (std::mem::replace(&mut directives.current, default.clone()), default)
+        };
+        let audit = || warn!(expired = %expired, directives = %default, "Temporary log filter expired");
+        if let Err(e) = self.apply(&default, audit) {
+            tracing::error!(error = %e, "Failed to restore log filter");
+        }
+    }
+}
+
+/// Splits a unix timestamp into UTC `[year, month, day, hour, minute, second]`.
+pub(crate) fn utc_datetime(secs: u64) -> [u64; 6] {
+    // Howard Hinnant's civil_from_days
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    [year, month, day, rem / 3600, rem % 3600 / 60, rem % 60]
+}
+
+/// A formatting layer writing `format` to `writer`, with secret and PII
+/// fields masked.
+pub fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Full => layer
+            .fmt_fields(RedactingFields::new(DefaultFields::new()))
+            .with_target(false)
+            .boxed(),
+        LogFormat::Pretty => layer
+            .pretty()
+            .fmt_fields(RedactingFields::new(PrettyFields::new()))
+            .with_target(false)
+            .boxed(),
+        LogFormat::Compact => layer
+            .compact()
+            .fmt_fields(RedactingFields::new(DefaultFields::new()))
+            .with_target(false)
+            .boxed(),
+        LogFormat::Json => layer.fmt_fields(JsonFields::new()).event_format(FlatJson).boxed(),
+    }
+}
+
+/// The writer for a sink: stdout, stderr or a rotating file.
+pub(crate) fn sink_writer(sink: &LogSinkConfig) -> Result<BoxMakeWriter, Box<dyn std::error::Error>> {
+    Ok(match sink.kind {
+        LogSinkKind::Stdout => BoxMakeWriter::new(std::io::stdout),
+        LogSinkKind::Stderr => BoxMakeWriter::new(std::io::stderr),
+        LogSinkKind::File => {
+            let path = sink.path.clone().ok_or("File log sink requires a path")?;
+            BoxMakeWriter::new(RotatingFile::new(path, sink.rotation, sink.max_size_bytes, sink.max_files)?)
+        }
+    })
+}
+
+fn sink_layer<S>(sink: &LogSinkConfig, default_format: LogFormat) -> Result<Box<dyn Layer<S> + Send + Sync>, Box<dyn std::error::Error>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let format = sink.format.unwrap_or(default_format);
+    let ansi = sink.kind != LogSinkKind::File;
+    Ok(fmt_layer(format, sink_writer(sink)?, ansi))
+}
+
+/// The filter directives `config` asks for: `level` for the application's
+/// own target, `filter` for everything else.
+pub fn config_directives(config: &LogConfig) -> String {
+    format!("hello_cargo={},{}", config.level, config.filter)
+}
+
+pub fn setup_logging(config: &LogConfig) -> Result<LoggingGuard, Box<dyn std::error::Error>> {
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok())
+        .unwrap_or_else(|| config_directives(config));
+    let (env_filter, reload_handle) = reload::Layer::new(EnvFilter::new(&directives));
+
+    let sinks = config
+        .sinks
+        .iter()
+        .map(|sink| sink_layer(sink, config.format))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tracer_provider = if config.otlp.enabled {
+        Some(telemetry::tracer_provider(&config.otlp)?)
+    } else {
+        None
+    };
 
     tracing_subscriber::registry()
         .with(env_filter)
-        .with(tracing_subscriber::fmt::layer().with_target(false))
-        .init();
+        // An empty `Vec` layer would disable every callsite
+        .with((!sinks.is_empty()).then_some(sinks))
+        .with(tracer_provider.as_ref().map(telemetry::layer))
+        .try_init()?;
+
+    // `try_init` above fails on a second call, so this is only ever set once
+    let _ = LOG_LEVEL_CONTROLLER.set(LogLevelController::new(reload_handle, directives));
 
-    Ok(())
-}
\ No newline at end of file
+    Ok(LoggingGuard { tracer_provider })
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::secret::{is_sensitive_field, mask_field};
+use super::utc_datetime;
+
+/// Formats each event as one JSON object per line. Fields of every enclosing
+/// span are merged into the top level, so keys such as `request_id` and
+/// `user_id` can be queried directly; the event's own fields win on conflict.
+/// Known secret and PII fields are masked.
+pub struct FlatJson;
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+/// The current time as RFC 3339 with milliseconds, in UTC.
+pub(crate) fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let [year, month, day, hour, minute, second] = utc_datetime(now.as_secs());
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hour, minute, second, now.subsec_millis()
+    )
+}
+
+impl<S> FormatEvent<S, JsonFields> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, JsonFields>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut object = Map::new();
+        object.insert("timestamp".to_string(), Value::from(timestamp()));
+        object.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        object.insert("target".to_string(), Value::from(metadata.target()));
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() {
+                    if let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(fields) {
+                        // `otel.*` fields only steer the OpenTelemetry layer
+                        object.extend(fields.into_iter().filter(|(key, _)| !key.starts_with("otel.")));
+                    }
+                }
+                object.insert("span".to_string(), Value::from(span.name()));
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+
+        for (key, value) in object.iter_mut() {
+            if is_sensitive_field(key) {
+                let raw = value.as_str().map(str::to_owned).unwrap_or_else(|| value.to_string());
+                *value = Value::from(mask_field(key, &raw).unwrap_or_default());
+            }
+        }
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::field::{MakeVisitor, VisitFmt, VisitOutput};
+
+use crate::secret::{is_sensitive_field, mask_field};
+
+/// Wraps a field formatter so values of known secret and PII fields are
+/// masked before they are written.
+pub struct RedactingFields<M>(M);
+
+impl<M> RedactingFields<M> {
+    pub fn new(inner: M) -> Self {
+        RedactingFields(inner)
+    }
+}
+
+impl<T, M: MakeVisitor<T>> MakeVisitor<T> for RedactingFields<M> {
+    type Visitor = Redacting<M::Visitor>;
+
+    fn make_visitor(&self, target: T) -> Self::Visitor {
+        Redacting(self.0.make_visitor(target))
+    }
+}
+
+pub struct Redacting<V>(V);
+
+impl<V: Visit> Redacting<V> {
+    /// Records the masked value if `field` needs masking; returns whether it
+    /// did. `value` is only rendered for fields that need it.
+    fn record_masked(&mut self, field: &Field, value: impl FnOnce() -> String) -> bool {
+        if !is_sensitive_field(field.name()) {
+            return false;
+        }
+        let masked = mask_field(field.name(), &value()).unwrap_or_default();
+        self.0.record_debug(field, &format_args!("{}", masked));
+        true
+    }
+}
+
+impl<V: Visit> Visit for Redacting<V> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if !self.record_masked(field, || value.to_string()) {
+            self.0.record_f64(field, value);
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if !self.record_masked(field, || value.to_string()) {
+            self.0.record_i64(field, value);
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if !self.record_masked(field, || value.to_string()) {
+            self.0.record_u64(field, value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.record_bool(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !self.record_masked(field, || value.to_string()) {
+            self.0.record_str(field, value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.record_error(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.record_masked(field, || format!("{:?}", value).trim_matches('"').to_string()) {
+            self.0.record_debug(field, value);
+        }
+    }
+}
+
+impl<V: VisitOutput<O>, O> VisitOutput<O> for Redacting<V> {
+    fn finish(self) -> O {
+        self.0.finish()
+    }
+}
+
+impl<V: VisitFmt> VisitFmt for Redacting<V> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        self.0.writer()
+    }
+}
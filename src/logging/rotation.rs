@@ -0,0 +1,156 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::LogRotation;
+use super::utc_datetime;
+
+struct State {
+    file: File,
+    size: u64,
+    period: Option<u64>,
+}
+
+/// A log file that is rotated by size and/or time. Rotated files are renamed
+/// to `<name>.<UTC timestamp>` next to the active file, and only the newest
+/// `max_files` of them are kept.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    max_size_bytes: Option<u64>,
+    max_files: usize,
+    state: Mutex<State>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn period_of(rotation: LogRotation, secs: u64) -> Option<u64> {
+    match rotation {
+        LogRotation::Never => None,
+        LogRotation::Hourly => Some(secs / 3600),
+        LogRotation::Daily => Some(secs / 86_400),
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl RotatingFile {
+    pub fn new(path: PathBuf, rotation: LogRotation, max_size_bytes: Option<u64>, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            state: Mutex::new(State { file, size, period: period_of(rotation, now_secs()) }),
+            path,
+            rotation,
+            max_size_bytes,
+            max_files,
+        })
+    }
+
+    fn rotated_prefix(&self) -> String {
+        let name = self.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        format!("{}.", name)
+    }
+
+    fn rotate(&self, state: &mut State, now: u64) -> io::Result<()> {
+        state.file.flush()?;
+
+        let [year, month, day, hour, minute, second] = utc_datetime(now);
+        let stamp = format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}", year, month, day, hour, minute, second);
+        let mut target = self.path.with_file_name(format!("{}{}", self.rotated_prefix(), stamp));
+        let mut sequence = 1;
+        while target.exists() {
+            target = self.path.with_file_name(format!("{}{}.{}", self.rotated_prefix(), stamp, sequence));
+            sequence += 1;
+        }
+        fs::rename(&self.path, &target)?;
+
+        state.file = open(&self.path)?;
+        state.size = 0;
+        self.prune()
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let dir = match self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        let prefix = self.rotated_prefix();
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // Oldest first: timestamps sort lexically, then same-second
+        // collisions by their numeric suffix
+        rotated.sort_by_cached_key(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let suffix = name[prefix.len()..].to_string();
+            match suffix.split_once('.') {
+                Some((stamp, sequence)) => (stamp.to_string(), sequence.parse::<u64>().unwrap_or(0)),
+                None => (suffix, 0),
+            }
+        });
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for old in &rotated[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+
+    fn write_locked(&self, state: &mut State, buf: &[u8]) -> io::Result<usize> {
+        let now = now_secs();
+        let period = period_of(self.rotation, now);
+        let period_elapsed = period != state.period;
+        let too_large = self
+            .max_size_bytes
+            .is_some_and(|max| state.size > 0 && state.size + buf.len() as u64 > max);
+        if period_elapsed || too_large {
+            self.rotate(state, now)?;
+            state.period = period;
+        }
+
+        let written = state.file.write(buf)?;
+        state.size += written as u64;
+        Ok(written)
+    }
+}
+
+pub struct RotatingFileWriter<'a> {
+    file: &'a RotatingFile,
+    state: MutexGuard<'a, State>,
+}
+
+impl Write for RotatingFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write_locked(&mut self.state, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = RotatingFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RotatingFileWriter {
+            file: self,
+            state: self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+}
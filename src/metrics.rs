@@ -0,0 +1,202 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::auth;
+use crate::repositories::UserRepositoryArc;
+
+/// Label used for requests that matched no route, so arbitrary paths cannot
+/// create new series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests_total: IntCounterVec,
+    http_request_duration_seconds: HistogramVec,
+    http_requests_in_flight: IntGauge,
+    repository_operation_duration_seconds: HistogramVec,
+    repository_errors_total: IntCounterVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_size: IntGauge,
+    db_pool_wait_total: IntCounter,
+    db_pool_wait_seconds_total: Counter,
+    /// Held while the pool counters catch up with the pool's totals, so
+    /// concurrent scrapes don't both add the same difference.
+    pool_totals: Mutex<()>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total HTTP requests by route template and status"),
+            &["method", "route", "status"],
+        ).unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency in seconds"),
+            &["method", "route", "status"],
+        ).unwrap();
+        let http_requests_in_flight = IntGauge::new("http_requests_in_flight", "HTTP requests currently being served").unwrap();
+        let repository_operation_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("repository_operation_duration_seconds", "Repository operation latency in seconds"),
+            &["operation"],
+        ).unwrap();
+        let repository_errors_total = IntCounterVec::new(
+            Opts::new("repository_errors_total", "Repository operations that returned an error"),
+            &["operation"],
+        ).unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Database pool connections by state"),
+            &["state"],
+        ).unwrap();
+        let db_pool_max_size = IntGauge::new("db_pool_max_size", "Maximum size of the database pool").unwrap();
+        let db_pool_wait_total = IntCounter::new("db_pool_wait_total", "Connections checked out of the database pool").unwrap();
+        let db_pool_wait_seconds_total = Counter::new(
+            "db_pool_wait_seconds_total",
+            "Total time spent waiting for a database pool connection",
+        ).unwrap();
+
+        registry.register(Box::new(http_requests_total.clone())).unwrap();
+        registry.register(Box::new(http_request_duration_seconds.clone())).unwrap();
+        registry.register(Box::new(http_requests_in_flight.clone())).unwrap();
+        registry.register(Box::new(repository_operation_duration_seconds.clone())).unwrap();
+        registry.register(Box::new(repository_errors_total.clone())).unwrap();
+        registry.register(Box::new(db_pool_connections.clone())).unwrap();
+        registry.register(Box::new(db_pool_max_size.clone())).unwrap();
+        registry.register(Box::new(db_pool_wait_total.clone())).unwrap();
+        registry.register(Box::new(db_pool_wait_seconds_total.clone())).unwrap();
+
+        Metrics {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            http_requests_in_flight,
+            repository_operation_duration_seconds,
+            repository_errors_total,
+            db_pool_connections,
+            db_pool_max_size,
+            db_pool_wait_total,
+            db_pool_wait_seconds_total,
+            pool_totals: Mutex::new(()),
+        }
+    }
+
+    pub fn observe_repository_operation(&self, operation: &str, started: Instant, failed: bool) {
+        self.repository_operation_duration_seconds
+            .with_label_values(&[operation])
+            .observe(started.elapsed().as_secs_f64());
+        if failed {
+            self.repository_errors_total.with_label_values(&[operation]).inc();
+        }
+    }
+
+    fn record_pool_stats(&self, user_repository: &UserRepositoryArc) {
+        if let Some(pool) = user_repository.pool_stats() {
+            let active = pool.connections.saturating_sub(pool.idle_connections);
+            self.db_pool_connections.with_label_values(&["idle"]).set(pool.idle_connections as i64);
+            self.db_pool_connections.with_label_values(&["active"]).set(active as i64);
+            self.db_pool_max_size.set(pool.max_size as i64);
+            // The pool keeps running totals; advance the counters to match them.
+            let _totals = self.pool_totals.lock().unwrap();
+            self.db_pool_wait_total.inc_by(pool.wait_count.saturating_sub(self.db_pool_wait_total.get()));
+            let waited = pool.wait_seconds_total - self.db_pool_wait_seconds_total.get();
+            if waited > 0.0 {
+                self.db_pool_wait_seconds_total.inc_by(waited);
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        buffer
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collapses non-standard methods into one label value to bound cardinality.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+/// Decrements the in-flight gauge even if the request future is dropped.
+struct InFlightGuard(IntGauge);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+pub async fn track_http(State(metrics): State<Arc<Metrics>>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let method = method_label(request.method());
+
+    metrics.http_requests_in_flight.inc();
+    let _in_flight = InFlightGuard(metrics.http_requests_in_flight.clone());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method, route.as_str(), status.as_str()];
+    metrics.http_requests_total.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration_seconds
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+struct MetricsState {
+    metrics: Arc<Metrics>,
+    user_repository: UserRepositoryArc,
+}
+
+async fn scrape(State(state): State<Arc<MetricsState>>) -> impl IntoResponse {
+    state.metrics.record_pool_stats(&state.user_repository);
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        state.metrics.encode(),
+    )
+}
+
+pub fn router(metrics: Arc<Metrics>, user_repository: UserRepositoryArc) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .layer(axum::middleware::from_fn(auth::require_admin))
+        .with_state(Arc::new(MetricsState { metrics, user_repository }))
+}
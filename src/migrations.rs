@@ -0,0 +1,162 @@
+use std::fmt;
+use diesel::migration::{Migration, MigrationSource};
+use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::BigInt;
+use diesel::RunQueryDsl;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+use tracing::info;
+
+/// The `migrations/` directory, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Postgres advisory lock held while migrating, so replicas starting
+/// together run migrations one at a time. Arbitrary, but must not change.
+const LOCK_KEY: i64 = 0x6865_6c6c_6f5f_6361;
+
+pub type MigrationError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    /// Embedded migrations applied to the database, oldest first.
+    pub applied: Vec<String>,
+    /// Embedded migrations not yet applied, oldest first.
+    pub pending: Vec<String>,
+    /// Versions applied to the database that this binary doesn't know,
+    /// meaning the schema was migrated by a newer release.
+    pub unknown: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Whether the database schema matches what this binary expects.
+    pub fn check(&self) -> Result<(), SchemaMismatch> {
+        if self.pending.is_empty() && self.unknown.is_empty() {
+            return Ok(());
+        }
+        Err(SchemaMismatch { pending: self.pending.clone(), unknown: self.unknown.clone() })
+    }
+}
+
+/// The database schema is older or newer than this binary expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaMismatch {
+    pub pending: Vec<String>,
+    pub unknown: Vec<String>,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.unknown.is_empty() {
+            write!(
+                f,
+                "Database schema is newer than this binary expects; unknown migration versions: {}",
+                self.unknown.join(", ")
+            )?;
+            if !self.pending.is_empty() {
+                write!(f, "; ")?;
+            }
+        }
+        if !self.pending.is_empty() {
+            write!(
+                f,
+                "Database schema is older than this binary expects; pending migrations: {}. \
+                 Run `hello_cargo migrate up` or set database.auto_migrate = true",
+                self.pending.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaMismatch {}
+
+/// Names of the embedded migrations, oldest first.
+pub fn embedded() -> Result<Vec<String>, MigrationError> {
+    let mut names: Vec<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+        .iter()
+        .map(|migration| name(migration.as_ref()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn name(migration: &dyn Migration<Pg>) -> String {
+    migration.name().to_string()
+}
+
+/// Applied, pending and unknown migrations.
+pub fn status(conn: &mut PgConnection) -> Result<MigrationReport, MigrationError> {
+    let applied_versions: Vec<String> = conn.applied_migrations()?.iter().map(ToString::to_string).collect();
+    let mut migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?;
+    migrations.sort_by_key(|migration| name(migration.as_ref()));
+
+    let mut report = MigrationReport::default();
+    for migration in &migrations {
+        if applied_versions.contains(&migration.name().version().to_string()) {
+            report.applied.push(name(migration.as_ref()));
+        } else {
+            report.pending.push(name(migration.as_ref()));
+        }
+    }
+    let known: Vec<String> = migrations.iter().map(|migration| migration.name().version().to_string()).collect();
+    report.unknown = applied_versions.into_iter().filter(|version| !known.contains(version)).collect();
+    report.unknown.sort();
+    Ok(report)
+}
+
+/// Runs every pending migration, returning the names of those applied.
+pub fn up(conn: &mut PgConnection) -> Result<Vec<String>, MigrationError> {
+    with_lock(conn, run_pending)
+}
+
+fn run_pending(conn: &mut PgConnection) -> Result<Vec<String>, MigrationError> {
+    let pending: Vec<String> = conn.pending_migrations(MIGRATIONS)?.iter().map(|migration| name(migration.as_ref())).collect();
+    conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(pending)
+}
+
+/// Reverts the last `steps` migrations, returning the names of those reverted.
+pub fn down(conn: &mut PgConnection, steps: usize) -> Result<Vec<String>, MigrationError> {
+    with_lock(conn, |conn| {
+        let mut applied = status(conn)?.applied;
+        let mut reverted = Vec::new();
+        for _ in 0..steps {
+            let Some(last) = applied.pop() else {
+                break;
+            };
+            conn.revert_last_migration(MIGRATIONS)?;
+            reverted.push(last);
+        }
+        Ok(reverted)
+    })
+}
+
+/// Brings the schema up to date if `auto_migrate` is set, then checks that
+/// it matches this binary. Fails with a [`SchemaMismatch`] listing the
+/// offending migrations otherwise.
+pub fn prepare(conn: &mut PgConnection, auto_migrate: bool) -> Result<(), MigrationError> {
+    // Holding the lock while checking means a replica never sees another
+    // one's migration half done.
+    with_lock(conn, |conn| {
+        let report = status(conn)?;
+        if auto_migrate && report.unknown.is_empty() && !report.pending.is_empty() {
+            for migration in run_pending(conn)? {
+                info!(migration = %migration, "Applied migration");
+            }
+            return Ok(status(conn)?.check()?);
+        }
+        Ok(report.check()?)
+    })
+}
+
+fn with_lock<T>(
+    conn: &mut PgConnection,
+    f: impl FnOnce(&mut PgConnection) -> Result<T, MigrationError>,
+) -> Result<T, MigrationError> {
+    diesel::sql_query("SELECT pg_advisory_lock($1)").bind::<BigInt, _>(LOCK_KEY).execute(conn)?;
+    let result = f(conn);
+    let unlocked = diesel::sql_query("SELECT pg_advisory_unlock($1)").bind::<BigInt, _>(LOCK_KEY).execute(conn);
+    let value = result?;
+    unlocked?;
+    Ok(value)
+}
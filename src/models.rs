@@ -1,3 +1,4 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use diesel::prelude::*;
@@ -14,6 +15,16 @@ pub struct User {
     pub email: String,
 }
 
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("email", &crate::secret::mask_email(&self.email))
+            .finish()
+    }
+}
+
 impl User {
     pub fn new(id: Option<String>, name: String, email: String) -> Self {
         User {
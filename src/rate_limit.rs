@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{Extensions, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ipnet::IpNet;
+use serde_json::json;
+
+use crate::auth::{Principal, PrincipalKind};
+use crate::config::RateLimitConfig;
+
+/// Buckets are only swept once the table grows past this many clients.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Copy)]
+struct Quota {
+    per_second: f64,
+    burst: u32,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, quota: Quota, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.per_second).min(quota.burst as f64);
+        self.updated = now;
+    }
+}
+
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next request would be admitted.
+    retry_after: u64,
+}
+
+/// Token-bucket rate limiter keyed on client identity and, for routes with
+/// an override, the route itself.
+pub struct RateLimiter {
+    config: RwLock<Arc<RateLimitConfig>>,
+    // Bucket key is (client, index into `config.routes`); `None` is the default quota.
+    buckets: Mutex<HashMap<(String, Option<usize>), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config: RwLock::new(Arc::new(config)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn config(&self) -> Arc<RateLimitConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Swaps in new limits. Buckets are reset, since route indexes and
+    /// quotas may no longer match.
+    pub fn update(&self, config: RateLimitConfig) {
+        *self.config.write().unwrap() = Arc::new(config);
+        self.buckets.lock().unwrap().clear();
+    }
+
+    fn quota_for(config: &RateLimitConfig, method: &Method, route: Option<&str>) -> (Option<usize>, Quota) {
+        let route_override = route.and_then(|route| {
+            config.routes.iter().position(|r| {
+                r.path == route
+                    && r.method.as_deref().is_none_or(|m| m.eq_ignore_ascii_case(method.as_str()))
+            })
+        });
+        match route_override {
+            Some(index) => {
+                let r = &config.routes[index];
+                (Some(index), Quota { per_second: r.per_second, burst: r.burst })
+            }
+            None => (None, Quota { per_second: config.per_second, burst: config.burst }),
+        }
+    }
+
+    fn check(&self, config: &RateLimitConfig, client: String, route: Option<usize>, quota: Quota) -> Decision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= SWEEP_THRESHOLD {
+            // A bucket that has refilled completely carries no state worth keeping.
+            buckets.retain(|(_, route), bucket| {
+                let quota = match route {
+                    // Left over from before an update raced with the reset
+                    Some(index) => match config.routes.get(*index) {
+                        Some(r) => Quota { per_second: r.per_second, burst: r.burst },
+                        None => return false,
+                    },
+                    None => Quota { per_second: config.per_second, burst: config.burst },
+                };
+                bucket.refill(quota, now);
+                bucket.tokens < quota.burst as f64
+            });
+        }
+
+        let bucket = buckets
+            .entry((client, route))
+            .or_insert_with(|| Bucket { tokens: quota.burst as f64, updated: now });
+        bucket.refill(quota, now);
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let reset = ((quota.burst as f64 - bucket.tokens) / quota.per_second).ceil() as u64;
+        let retry_after = if allowed { 0 } else { ((1.0 - bucket.tokens) / quota.per_second).ceil().max(1.0) as u64 };
+
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset,
+            retry_after,
+        }
+    }
+}
+
+/// Marks requests that came in on a Unix socket listener whose peers are
+/// trusted proxies (`trust_forwarded`).
+#[derive(Debug, Clone, Copy)]
+pub struct TrustedUnixPeer;
+
+fn is_trusted(ip: IpAddr, trusted_proxies: &[IpNet]) -> bool {
+    trusted_proxies.iter().any(|net| net.contains(&ip))
+}
+
+/// Walks `X-Forwarded-For` from the right, past trusted proxies, to the
+/// first address that isn't one. `client` is the answer if there's none.
+fn forwarded_client(headers: &HeaderMap, mut client: Option<IpAddr>, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    for hop in forwarded.iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(ip) => {
+                client = Some(ip);
+                if !is_trusted(ip, trusted_proxies) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Resolves the originating client address. `X-Forwarded-For` is only
+/// consulted when the peer is a trusted proxy, and is walked from the right
+/// so that entries prepended by the client cannot spoof the result.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !is_trusted(peer, trusted_proxies) {
+        return Some(peer);
+    }
+    forwarded_client(headers, Some(peer), trusted_proxies)
+}
+
+/// [`client_ip`] for `request`, whose peer is the TCP address in
+/// `ConnectInfo` or, on a [`TrustedUnixPeer`] socket, a trusted proxy.
+pub fn request_client_ip(extensions: &Extensions, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    if let Some(ConnectInfo(peer)) = extensions.get::<ConnectInfo<SocketAddr>>() {
+        return client_ip(headers, Some(peer.ip()), trusted_proxies);
+    }
+    if extensions.get::<TrustedUnixPeer>().is_some() {
+        return forwarded_client(headers, None, trusted_proxies);
+    }
+    None
+}
+
+fn client_key(request: &Request, trusted_proxies: &[IpNet]) -> String {
+    if let Some(principal) = request.extensions().get::<Principal>() {
+        return match principal.kind {
+            PrincipalKind::ApiKey => format!("api_key:{}", principal.name),
+            PrincipalKind::ClientCertificate => format!("client_certificate:{}", principal.name),
+        };
+    }
+
+    match request_client_ip(request.extensions(), request.headers(), trusted_proxies) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+fn set_header(headers: &mut HeaderMap, name: &'static str, value: u64) {
+    headers.insert(name, HeaderValue::from(value));
+}
+
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = limiter.config();
+    if !config.enabled {
+        return next.run(request).await;
+    }
+
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned());
+    let (route_index, quota) = RateLimiter::quota_for(&config, request.method(), route.as_deref());
+    let client = client_key(&request, &config.trusted_proxies);
+    let decision = limiter.check(&config, client, route_index, quota);
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        (StatusCode::TOO_MANY_REQUESTS, Json(json!({"error": "Too many requests"}))).into_response()
+    };
+
+    let headers = response.headers_mut();
+    set_header(headers, "ratelimit-limit", decision.limit as u64);
+    set_header(headers, "ratelimit-remaining", decision.remaining as u64);
+    set_header(headers, "ratelimit-reset", decision.reset);
+    if !decision.allowed {
+        set_header(headers, "retry-after", decision.retry_after);
+    }
+    response
+}
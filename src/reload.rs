@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::auth::Authenticator;
+use crate::config::{AppConfig, ConfigError, ConfigSources};
+use crate::cors::Cors;
+use crate::logging::{config_directives, log_level_controller};
+use crate::rate_limit::RateLimiter;
+
+/// What a reload changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Settings now in effect.
+    pub applied: Vec<&'static str>,
+    /// Settings that changed on disk but keep their running value until
+    /// the process restarts.
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Applies reloaded configuration to a running app. Only settings that can
+/// change safely mid-flight are applied; the rest are reported.
+pub struct ConfigReloader {
+    current: Mutex<AppConfig>,
+    sources: Mutex<ConfigSources>,
+    authenticators: [Arc<Authenticator>; 2],
+    rate_limiter: Arc<RateLimiter>,
+    cors: Arc<Cors>,
+}
+
+impl ConfigReloader {
+    pub(crate) fn new(
+        config: AppConfig,
+        sources: ConfigSources,
+        authenticators: [Arc<Authenticator>; 2],
+        rate_limiter: Arc<RateLimiter>,
+        cors: Arc<Cors>,
+    ) -> Self {
+        ConfigReloader {
+            current: Mutex::new(config),
+            sources: Mutex::new(sources),
+            authenticators,
+            rate_limiter,
+            cors,
+        }
+    }
+
+    /// The configuration in effect.
+    pub fn current(&self) -> AppConfig {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// The layer each value of the last loaded configuration came from.
+    pub fn sources(&self) -> ConfigSources {
+        self.sources.lock().unwrap().clone()
+    }
+
+    /// Applies `config`, which must already be validated.
+    pub fn apply(&self, config: AppConfig) -> ReloadReport {
+        let mut current = self.current.lock().unwrap();
+        let mut report = ReloadReport::default();
+
+        if current.log.level != config.log.level || current.log.filter != config.log.filter {
+            if let Some(controller) = log_level_controller() {
+                if let Err(error) = controller.set_default(&config_directives(&config.log)) {
+                    error!("Failed to apply reloaded log filter: {}", error);
+                }
+            }
+            if current.log.level != config.log.level {
+                report.applied.push("log.level");
+            }
+            if current.log.filter != config.log.filter {
+                report.applied.push("log.filter");
+            }
+            current.log.level = config.log.level.clone();
+            current.log.filter = config.log.filter.clone();
+        }
+        if current.auth != config.auth {
+            for authenticator in &self.authenticators {
+                authenticator.update(config.auth.clone());
+            }
+            current.auth = config.auth.clone();
+            report.applied.push("auth");
+        }
+        if current.rate_limit != config.rate_limit {
+            self.rate_limiter.update(config.rate_limit.clone());
+            current.rate_limit = config.rate_limit.clone();
+            report.applied.push("rate_limit");
+        }
+        if current.cors != config.cors {
+            self.cors.update(config.cors.clone());
+            current.cors = config.cors.clone();
+            report.applied.push("cors");
+        }
+
+        let restart_required = [
+            ("server.host", current.server.host != config.server.host),
+            ("server.port", current.server.port != config.server.port),
+            ("server.request_timeout_seconds", current.server.request_timeout_seconds != config.server.request_timeout_seconds),
+            ("server.body_limit_bytes", current.server.body_limit_bytes != config.server.body_limit_bytes),
+            ("server.shutdown_grace_seconds", current.server.shutdown_grace_seconds != config.server.shutdown_grace_seconds),
+            ("server.tls", current.server.tls != config.server.tls),
+            ("server.listeners", current.server.listeners != config.server.listeners),
+            ("server.compression", current.server.compression != config.server.compression),
+            ("server.max_concurrent_requests", current.server.max_concurrent_requests != config.server.max_concurrent_requests),
+            ("server.routes", current.server.routes != config.server.routes),
+            ("server.http", current.server.http != config.server.http),
+            ("server.security_headers", current.server.security_headers != config.server.security_headers),
+            ("docs", current.docs != config.docs),
+            ("log.format", current.log.format != config.log.format),
+            ("log.sinks", current.log.sinks != config.log.sinks),
+            ("log.otlp", current.log.otlp != config.log.otlp),
+            ("log.access", current.log.access != config.log.access),
+            ("database.url", current.database.url != config.database.url),
+            ("database.host", current.database.host != config.database.host),
+            ("database.port", current.database.port != config.database.port),
+            ("database.user", current.database.user != config.database.user),
+            ("database.password", current.database.password != config.database.password),
+            ("database.password_file", current.database.password_file != config.database.password_file),
+            ("database.dbname", current.database.dbname != config.database.dbname),
+            ("database.auto_migrate", current.database.auto_migrate != config.database.auto_migrate),
+            ("database.pool", current.database.pool != config.database.pool),
+        ];
+        report.restart_required = restart_required
+            .into_iter()
+            .filter_map(|(key, changed)| changed.then_some(key))
+            .collect();
+
+        report
+    }
+
+    /// Loads and applies new configuration. A config that fails to load or
+    /// validate is rejected and the running configuration is left as is.
+    pub fn reload(
+        &self,
+        load: impl FnOnce() -> Result<(AppConfig, ConfigSources), ConfigError>,
+    ) -> Result<ReloadReport, ConfigError> {
+        let (config, mut sources) = load()?;
+        let report = self.apply(config);
+        // Settings waiting for a restart still run with their old values
+        let mut current = self.sources.lock().unwrap();
+        for key in &report.restart_required {
+            sources.keep(&current, key);
+        }
+        *current = sources;
+        Ok(report)
+    }
+}
+
+/// Modification time and size of every `*.toml` file in `dir`.
+fn snapshot(dir: &Path) -> BTreeMap<PathBuf, (Option<SystemTime>, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .filter_map(|path| {
+            let metadata = std::fs::metadata(&path).ok()?;
+            Some((path, (metadata.modified().ok(), metadata.len())))
+        })
+        .collect()
+}
+
+/// Reloads the configuration on SIGHUP, or when a `*.toml` file in `dir`
+/// is added, removed or modified (checked every `poll`). Runs until dropped.
+pub async fn watch(
+    reloader: Arc<ConfigReloader>,
+    dir: PathBuf,
+    poll: Duration,
+    load: impl Fn() -> Result<(AppConfig, ConfigSources), ConfigError>,
+) -> std::io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut files = snapshot(&dir);
+    let mut interval = tokio::time::interval(poll);
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => info!("Received SIGHUP, reloading configuration"),
+            _ = interval.tick() => {
+                let current = snapshot(&dir);
+                if current == files {
+                    continue;
+                }
+                files = current;
+                info!("Configuration files changed, reloading configuration");
+            }
+        }
+
+        match reloader.reload(&load) {
+            Ok(report) => {
+                if report.applied.is_empty() && report.restart_required.is_empty() {
+                    info!("Configuration reloaded, nothing changed");
+                }
+                if !report.applied.is_empty() {
+                    info!(settings = ?report.applied, "Applied reloaded configuration");
+                }
+                if !report.restart_required.is_empty() {
+                    warn!(settings = ?report.restart_required, "Configuration changes need a restart to take effect");
+                }
+            }
+            Err(error) => error!("Rejected configuration reload, keeping the running configuration: {}", error),
+        }
+    }
+}
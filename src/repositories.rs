@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use serde::Serialize;
 use std::sync::Arc;
 use crate::models::User;
 
@@ -7,12 +8,54 @@ pub type UserRepositoryArc = Arc<dyn UserRepository>;
 // Re-export specific implementations
 pub mod in_memory_repository;
 pub mod postgres_repository;
+pub mod metered_repository;
 
 #[async_trait]
 pub trait UserRepository: Send + Sync {
-    async fn get_all(&self) -> Vec<User>;
-    async fn get(&self, id: &str) -> Option<User>;
+    async fn get_all(&self) -> Result<Vec<User>, String>;
+    /// `Ok(None)` if there's no user `id`.
+    async fn get(&self, id: &str) -> Result<Option<User>, String>;
     async fn create(&self, user: User) -> Result<User, String>;
     async fn update(&self, id: &str, user: User) -> Result<(), String>;
-    async fn delete(&self, id: &str) -> bool;
+    /// Whether the user `id` existed.
+    async fn delete(&self, id: &str) -> Result<bool, String>;
+    async fn health(&self) -> RepositoryHealth;
+
+    /// Connection pool statistics, for backends that pool connections.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepositoryHealth {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub migrations: Option<MigrationStatus>,
+}
+
+impl RepositoryHealth {
+    pub fn healthy() -> Self {
+        RepositoryHealth { healthy: true, error: None, pool: None, migrations: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    /// Connections handed out by the pool so far.
+    pub wait_count: u64,
+    /// Cumulative time callers spent waiting for those connections.
+    pub wait_seconds_total: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub pending: Vec<String>,
 }
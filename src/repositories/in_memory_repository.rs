@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use tokio::sync::RwLock;
 use async_trait::async_trait;
 use crate::models::User;
-use super::UserRepository;
+use super::{RepositoryHealth, UserRepository};
 use ulid::Ulid;
 
 pub struct InMemoryUserRepository {
@@ -17,18 +17,27 @@ impl InMemoryUserRepository {
     }
 }
 
+impl Default for InMemoryUserRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl UserRepository for InMemoryUserRepository {
-    async fn get_all(&self) -> Vec<User> {
+    #[tracing::instrument(skip(self))]
+    async fn get_all(&self) -> Result<Vec<User>, String> {
         let users = self.users.read().await;
-        users.values().cloned().collect()
+        Ok(users.values().cloned().collect())
     }
 
-    async fn get(&self, id: &str) -> Option<User> {
+    #[tracing::instrument(skip(self))]
+    async fn get(&self, id: &str) -> Result<Option<User>, String> {
         let users = self.users.read().await;
-        users.get(id).cloned()
+        Ok(users.get(id).cloned())
     }
 
+    #[tracing::instrument(skip(self, user))]
     async fn create(&self, mut user: User) -> Result<User, String> {
         let mut users = self.users.write().await;
         if user.id.is_empty() {
@@ -43,6 +52,7 @@ impl UserRepository for InMemoryUserRepository {
         }
     }
 
+    #[tracing::instrument(skip(self, user))]
     async fn update(&self, id: &str, user: User) -> Result<(), String> {
         let mut users = self.users.write().await;
         if user.id != id && users.contains_key(&user.id) {
@@ -57,8 +67,14 @@ impl UserRepository for InMemoryUserRepository {
         }
     }
 
-    async fn delete(&self, id: &str) -> bool {
+    #[tracing::instrument(skip(self))]
+    async fn delete(&self, id: &str) -> Result<bool, String> {
         let mut users = self.users.write().await;
-        users.remove(id).is_some()
+        Ok(users.remove(id).is_some())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn health(&self) -> RepositoryHealth {
+        RepositoryHealth::healthy()
     }
 }
\ No newline at end of file
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use std::time::Instant;
+use async_trait::async_trait;
+use crate::metrics::Metrics;
+use crate::models::User;
+use super::{PoolStats, RepositoryHealth, UserRepository, UserRepositoryArc};
+
+/// Decorates another repository with latency and error metrics.
+pub struct MeteredUserRepository {
+    inner: UserRepositoryArc,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredUserRepository {
+    pub fn new(inner: UserRepositoryArc, metrics: Arc<Metrics>) -> Self {
+        MeteredUserRepository { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl UserRepository for MeteredUserRepository {
+    async fn get_all(&self) -> Result<Vec<User>, String> {
+        let started = Instant::now();
+        let result = self.inner.get_all().await;
+        self.metrics.observe_repository_operation("get_all", started, result.is_err());
+        result
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<User>, String> {
+        let started = Instant::now();
+        let result = self.inner.get(id).await;
+        self.metrics.observe_repository_operation("get", started, result.is_err());
+        result
+    }
+
+    async fn create(&self, user: User) -> Result<User, String> {
+        let started = Instant::now();
+        let result = self.inner.create(user).await;
+        self.metrics.observe_repository_operation("create", started, result.is_err());
+        result
+    }
+
+    async fn update(&self, id: &str, user: User) -> Result<(), String> {
+        let started = Instant::now();
+        let result = self.inner.update(id, user).await;
+        self.metrics.observe_repository_operation("update", started, result.is_err());
+        result
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, String> {
+        let started = Instant::now();
+        let result = self.inner.delete(id).await;
+        self.metrics.observe_repository_operation("delete", started, result.is_err());
+        result
+    }
+
+    async fn health(&self) -> RepositoryHealth {
+        self.inner.health().await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.inner.pool_stats()
+    }
+}
@@ -1,68 +1,222 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{self, ManageConnection, Pool, PooledConnection, R2D2Connection};
+use diesel_migrations::MigrationHarness;
 use ulid::Ulid;
+use crate::config::DatabaseConfig;
+use crate::migrations::MIGRATIONS;
 use crate::models::User;
 use crate::schema::users;
-use super::UserRepository;
+use super::{MigrationStatus, PoolStats, RepositoryHealth, UserRepository};
 
-pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type DbPool = Pool<PgConnectionManager>;
+
+/// Readiness probes must answer quickly, so health checks don't wait out the
+/// pool's full connection timeout.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Resolves the connection URL each time a connection is opened, so new
+/// connections use a password rotated in `database.password_file`.
+pub struct PgConnectionManager {
+    config: DatabaseConfig,
+}
+
+impl ManageConnection for PgConnectionManager {
+    type Connection = PgConnection;
+    type Error = r2d2::Error;
+
+    fn connect(&self) -> Result<PgConnection, r2d2::Error> {
+        let url = self
+            .config
+            .connection_url()
+            .map_err(|message| r2d2::Error::ConnectionError(ConnectionError::BadConnection(message)))?;
+        PgConnection::establish(url.expose()).map_err(r2d2::Error::ConnectionError)
+    }
+
+    fn is_valid(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.ping().map_err(r2d2::Error::QueryError)
+    }
+
+    fn has_broken(&self, conn: &mut PgConnection) -> bool {
+        std::thread::panicking() || conn.is_broken()
+    }
+}
+
+/// How often, and for how long in total, callers waited for a connection.
+#[derive(Default)]
+struct PoolWaits {
+    count: AtomicU64,
+    nanos: AtomicU64,
+}
 
 pub struct PostgresUserRepository {
     pool: DbPool,
+    waits: Arc<PoolWaits>,
+}
+
+/// Checks a connection out of `pool`, recording how long it took.
+fn checkout(pool: &DbPool, waits: &PoolWaits) -> Result<PooledConnection<PgConnectionManager>, String> {
+    let started = Instant::now();
+    let conn = pool.get().map_err(|e| format!("Couldn't get db connection from pool: {}", e))?;
+    waits.count.fetch_add(1, Ordering::Relaxed);
+    waits.nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    Ok(conn)
+}
+
+fn check_health(pool: &DbPool, stats: Option<PoolStats>) -> RepositoryHealth {
+    let unhealthy = |error: String| RepositoryHealth { healthy: false, error: Some(error), pool: stats.clone(), migrations: None };
+    let conn = &mut match pool.get_timeout(HEALTH_CHECK_TIMEOUT) {
+        Ok(conn) => conn,
+        Err(e) => return unhealthy(e.to_string()),
+    };
+
+    if let Err(e) = diesel::sql_query("SELECT 1").execute(conn) {
+        return unhealthy(e.to_string());
+    }
+
+    match conn.pending_migrations(MIGRATIONS) {
+        Ok(pending) => {
+            let pending: Vec<String> = pending.iter().map(|m| m.name().to_string()).collect();
+            RepositoryHealth {
+                healthy: pending.is_empty(),
+                error: (!pending.is_empty()).then(|| "Database has pending migrations".to_string()),
+                pool: stats,
+                migrations: Some(MigrationStatus { pending }),
+            }
+        }
+        Err(e) => unhealthy(e.to_string()),
+    }
 }
 
 impl PostgresUserRepository {
-    pub fn new(database_url: &str) -> Self {
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
+    /// Builds the pool, failing if its initial connections can't be opened
+    /// within the connection timeout.
+    pub fn connect(database: &DatabaseConfig) -> Result<Self, r2d2::PoolError> {
+        let manager = PgConnectionManager { config: database.clone() };
+        let config = &database.pool;
         let pool = Pool::builder()
-            .build(manager)
-            .expect("Failed to create pool.");
-        PostgresUserRepository { pool }
+            .max_size(config.max_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(Duration::from_secs(config.connection_timeout_seconds))
+            .idle_timeout(config.idle_timeout_seconds.map(Duration::from_secs))
+            .max_lifetime(config.max_lifetime_seconds.map(Duration::from_secs))
+            .build(manager)?;
+        Ok(PostgresUserRepository { pool, waits: Arc::default() })
+    }
+
+    fn connection(&self) -> Result<PooledConnection<PgConnectionManager>, String> {
+        checkout(&self.pool, &self.waits)
+    }
+
+    /// Runs `query` on a pooled connection on the blocking thread pool, so
+    /// waiting on the pool or the database doesn't stall the runtime.
+    async fn run<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PgConnection) -> Result<T, String> + Send + 'static,
+    {
+        let (pool, waits) = (self.pool.clone(), self.waits.clone());
+        tokio::task::spawn_blocking(move || query(&mut *checkout(&pool, &waits)?))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Every user, or why they couldn't be loaded.
+    pub fn list(&self) -> Result<Vec<User>, String> {
+        let conn = &mut self.connection()?;
+        users::table.load::<User>(conn).map_err(|e| format!("Error loading users: {}", e))
+    }
+
+    /// Deletes the user `id`, returning whether it existed.
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let conn = &mut self.connection()?;
+        diesel::delete(users::table.find(id))
+            .execute(conn)
+            .map(|affected| affected > 0)
+            .map_err(|e| e.to_string())
     }
 }
 
 #[async_trait]
 impl UserRepository for PostgresUserRepository {
-    async fn get_all(&self) -> Vec<User> {
-        let conn = &mut self.pool.get().expect("Couldn't get db connection from pool");
-        users::table.load::<User>(conn).expect("Error loading users")
+    #[tracing::instrument(skip(self), fields(db.system = "postgresql"))]
+    async fn get_all(&self) -> Result<Vec<User>, String> {
+        self.run(|conn| users::table.load::<User>(conn).map_err(|e| format!("Error loading users: {}", e))).await
     }
 
-    async fn get(&self, id: &str) -> Option<User> {
-        let conn = &mut self.pool.get().expect("Couldn't get db connection from pool");
-        users::table.find(id).first::<User>(conn).ok()
+    #[tracing::instrument(skip(self), fields(db.system = "postgresql"))]
+    async fn get(&self, id: &str) -> Result<Option<User>, String> {
+        let id = id.to_string();
+        self.run(move |conn| users::table.find(id).first::<User>(conn).optional().map_err(|e| e.to_string())).await
     }
 
+    #[tracing::instrument(skip(self, user), fields(db.system = "postgresql"))]
     async fn create(&self, mut user: User) -> Result<User, String> {
-        let conn = &mut self.pool.get().expect("Couldn't get db connection from pool");
         if user.id.is_empty() {
             user.id = Ulid::new().to_string();
         }
-        diesel::insert_into(users::table)
-            .values(&user)
-            .execute(conn)
-            .map_err(|e| e.to_string())?;
-        Ok(user)
+        self.run(move |conn| {
+            diesel::insert_into(users::table)
+                .values(&user)
+                .execute(conn)
+                .map_err(|e| e.to_string())?;
+            Ok(user)
+        })
+        .await
     }
 
+    #[tracing::instrument(skip(self, user), fields(db.system = "postgresql"))]
     async fn update(&self, id: &str, user: User) -> Result<(), String> {
-        let conn = &mut self.pool.get().expect("Couldn't get db connection from pool");
-        diesel::update(users::table.find(id))
-            .set((
-                users::name.eq(user.name),
-                users::email.eq(user.email),
-            ))
-            .execute(conn)
-            .map_err(|e| e.to_string())?;
-        Ok(())
+        let id = id.to_string();
+        self.run(move |conn| {
+            diesel::update(users::table.find(id))
+                .set((
+                    users::name.eq(user.name),
+                    users::email.eq(user.email),
+                ))
+                .execute(conn)
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await
     }
 
-    async fn delete(&self, id: &str) -> bool {
-        let conn = &mut self.pool.get().expect("Couldn't get db connection from pool");
-        diesel::delete(users::table.find(id))
-            .execute(conn)
-            .map(|affected| affected > 0)
-            .unwrap_or(false)
+    #[tracing::instrument(skip(self), fields(db.system = "postgresql"))]
+    async fn delete(&self, id: &str) -> Result<bool, String> {
+        let id = id.to_string();
+        self.run(move |conn| {
+            diesel::delete(users::table.find(id))
+                .execute(conn)
+                .map(|affected| affected > 0)
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self), fields(db.system = "postgresql"))]
+    async fn health(&self) -> RepositoryHealth {
+        let stats = self.pool_stats();
+        let pool = self.pool.clone();
+        // Waits up to HEALTH_CHECK_TIMEOUT while the database is down
+        tokio::task::spawn_blocking({
+            let stats = stats.clone();
+            move || check_health(&pool, stats)
+        })
+        .await
+        .unwrap_or_else(|e| RepositoryHealth { healthy: false, error: Some(e.to_string()), pool: stats, migrations: None })
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        let state = self.pool.state();
+        Some(PoolStats {
+            max_size: self.pool.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            wait_count: self.waits.count.load(Ordering::Relaxed),
+            wait_seconds_total: Duration::from_nanos(self.waits.nanos.load(Ordering::Relaxed)).as_secs_f64(),
+        })
     }
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use ulid::Ulid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error bodies larger than this are passed through without a request id.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The request's correlation id, stored in request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Caller-supplied ids end up in logs and response headers, so only accept
+/// short ids made of unambiguous characters.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Accepts the caller's `X-Request-Id` or generates a ULID, echoes it on the
+/// response, and adds it to JSON error bodies.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_owned)
+        .unwrap_or_else(|| Ulid::new().to_string());
+    let header_value = HeaderValue::from_str(&id).expect("request id is a valid header value");
+
+    request.headers_mut().insert(REQUEST_ID_HEADER, header_value.clone());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = add_to_error_body(response, &id).await;
+    }
+    response.headers_mut().insert(REQUEST_ID_HEADER, header_value);
+    response
+}
+
+async fn add_to_error_body(response: Response, id: &str) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    let is_small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= MAX_ERROR_BODY as u64);
+    if !is_json || !is_small {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut error)) => {
+            error.insert("request_id".to_string(), serde_json::Value::String(id.to_string()));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(error).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
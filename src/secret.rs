@@ -0,0 +1,87 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A configuration value that must not appear in logs. `Debug` and
+/// `Display` print `[REDACTED]`; use [`Secret::expose`] to read the value.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// Serializes as `[REDACTED]`, so printed configuration can't leak it.
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Field names whose values are masked in log output. Matching ignores case
+/// and any `prefix.` before the name.
+const SECRET_FIELDS: &[&str] = &["password", "secret", "token", "api_key", "authorization", "database_url"];
+const PII_EMAIL_FIELDS: &[&str] = &["email"];
+
+/// Masks an email address down to its first character and domain.
+pub fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{}***@{}", first, domain)
+        }
+        None => REDACTED.to_string(),
+    }
+}
+
+fn field_name(field: &str) -> String {
+    field.rsplit('.').next().unwrap_or(field).to_ascii_lowercase()
+}
+
+pub fn is_sensitive_field(field: &str) -> bool {
+    let name = field_name(field);
+    SECRET_FIELDS.contains(&name.as_str()) || PII_EMAIL_FIELDS.contains(&name.as_str())
+}
+
+/// The masked form of `value` if `field` is a known secret or PII field.
+pub fn mask_field(field: &str, value: &str) -> Option<String> {
+    let name = field_name(field);
+    if SECRET_FIELDS.contains(&name.as_str()) {
+        Some(REDACTED.to_string())
+    } else if PII_EMAIL_FIELDS.contains(&name.as_str()) {
+        Some(mask_email(value))
+    } else {
+        None
+    }
+}
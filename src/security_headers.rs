@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::error;
+
+use crate::config::{FrameOptions, SecurityHeadersConfig};
+
+/// Where Swagger UI is mounted.
+pub const DOCS_PATH: &str = "/swagger-ui";
+
+/// `SecurityHeadersConfig` turned into header values.
+pub struct SecurityHeaders {
+    /// Sent on every response.
+    common: Vec<(HeaderName, HeaderValue)>,
+    api_policy: Option<HeaderValue>,
+    docs_policy: Option<HeaderValue>,
+}
+
+fn header_value(key: &str, value: &str) -> Option<HeaderValue> {
+    if value.is_empty() {
+        return None;
+    }
+    // Validation rejects bad values; an unvalidated config just loses them
+    HeaderValue::from_str(value)
+        .inspect_err(|_| error!(value = %value, "Ignoring invalid security_headers.{}", key))
+        .ok()
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &SecurityHeadersConfig) -> Self {
+        if !config.enabled {
+            return SecurityHeaders { common: Vec::new(), api_policy: None, docs_policy: None };
+        }
+        let mut common = vec![
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+            (
+                header::X_FRAME_OPTIONS,
+                HeaderValue::from_static(match config.frame_options {
+                    FrameOptions::Deny => "DENY",
+                    FrameOptions::SameOrigin => "SAMEORIGIN",
+                }),
+            ),
+        ];
+        if config.hsts_max_age_seconds > 0 {
+            let subdomains = if config.hsts_include_subdomains { "; includeSubDomains" } else { "" };
+            let hsts = format!("max-age={}{}", config.hsts_max_age_seconds, subdomains);
+            common.push((header::STRICT_TRANSPORT_SECURITY, HeaderValue::from_str(&hsts).unwrap()));
+        }
+        if let Some(referrer_policy) = header_value("referrer_policy", &config.referrer_policy) {
+            common.push((header::REFERRER_POLICY, referrer_policy));
+        }
+        SecurityHeaders {
+            common,
+            api_policy: header_value("content_security_policy", &config.content_security_policy),
+            docs_policy: header_value("docs_content_security_policy", &config.docs_content_security_policy),
+        }
+    }
+}
+
+/// Adds the configured security headers to responses that don't set them
+/// already, with Swagger UI getting its own `Content-Security-Policy`.
+pub async fn security_headers(State(headers): State<Arc<SecurityHeaders>>, request: Request, next: Next) -> Response {
+    let docs = request.uri().path().starts_with(DOCS_PATH);
+    let mut response = next.run(request).await;
+    let policy = if docs { &headers.docs_policy } else { &headers.api_policy };
+    let response_headers = response.headers_mut();
+    for (name, value) in &headers.common {
+        response_headers.entry(name).or_insert_with(|| value.clone());
+    }
+    if let Some(policy) = policy {
+        response_headers.entry(header::CONTENT_SECURITY_POLICY).or_insert_with(|| policy.clone());
+    }
+    response
+}
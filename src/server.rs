@@ -0,0 +1,425 @@
+use std::fmt;
+use std::fs::Permissions;
+use std::net::SocketAddr;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::{extract::ConnectInfo, Router};
+use hyper::body::Incoming;
+use hyper::rt::{Read, Write};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use socket2::{Socket, Type};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+
+use crate::auth::ClientCertificate;
+use crate::config::{HttpConfig, HttpProtocol, ListenerConfig};
+use crate::tls::CertStore;
+
+/// Connections that haven't finished the TLS handshake by then are dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shared shutdown state. Once triggered, readiness fails and listeners
+/// stop accepting connections and start draining.
+#[derive(Clone)]
+pub struct Shutdown {
+    draining: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown { draining: Arc::new(watch::channel(false).0) }
+    }
+
+    /// Starts shutting down. Idempotent.
+    pub fn begin(&self) {
+        self.draining.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Resolves once [`Shutdown::begin`] has been called.
+    pub async fn draining(&self) {
+        let mut draining = self.draining.subscribe();
+        // The sender lives in self, so this can't fail
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+}
+
+/// SIGTERM and SIGINT, caught from the moment this is created, so a signal
+/// arriving during startup still leads to a graceful shutdown.
+pub struct ShutdownSignals {
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(ShutdownSignals { terminate: signal(SignalKind::terminate())?, interrupt: signal(SignalKind::interrupt())? })
+    }
+
+    /// Waits for the next signal, returning its name.
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.interrupt.recv() => "SIGINT",
+        }
+    }
+}
+
+/// A bound socket the server accepts connections on.
+pub enum Listener {
+    Tcp(TcpListener),
+    /// The socket file, if set, is removed when the listener is dropped.
+    /// Sockets passed in by systemd are left for it to clean up.
+    Unix(UnixListener, Option<SocketFile>),
+}
+
+/// A Unix socket file this process bound, identified by inode so that a
+/// file another process has since put at the same path is left alone.
+pub struct SocketFile {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+impl SocketFile {
+    fn new(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::symlink_metadata(path)?;
+        Ok(SocketFile { path: path.to_path_buf(), dev: metadata.dev(), ino: metadata.ino() })
+    }
+
+    fn is_ours(&self) -> bool {
+        std::fs::symlink_metadata(&self.path).is_ok_and(|metadata| metadata.dev() == self.dev && metadata.ino() == self.ino)
+    }
+}
+
+enum Accepted {
+    Tcp(TcpStream, SocketAddr),
+    Unix(UnixStream),
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+impl Listener {
+    /// Binds the address in `config`, replacing a stale Unix socket file
+    /// left behind by a previous run. A socket something still listens on
+    /// is an error, not replaced.
+    pub async fn bind(config: &ListenerConfig) -> std::io::Result<Self> {
+        if let Some(addr) = config.tcp {
+            return Ok(Listener::Tcp(TcpListener::bind(addr).await?));
+        }
+        let Some(path) = &config.unix else {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "listener has neither tcp nor unix set"));
+        };
+        if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => {
+                    let message = format!("{} is in use by another process", path.display());
+                    return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse, message));
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+                Err(error) => return Err(error),
+            }
+        }
+        let listener = match &config.mode {
+            Some(mode) => {
+                let mode = u32::from_str_radix(mode, 8)
+                    .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+                bind_with_mode(path, mode)?
+            }
+            None => UnixListener::bind(path)?,
+        };
+        Ok(Listener::Unix(listener, Some(SocketFile::new(path)?)))
+    }
+
+    /// Takes over a listening TCP or Unix socket opened by someone else,
+    /// e.g. systemd socket activation.
+    pub fn from_fd(fd: OwnedFd) -> std::io::Result<Self> {
+        let socket = Socket::from(fd);
+        if socket.r#type()? != Type::STREAM || !socket.is_listener()? {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a listening stream socket"));
+        }
+        socket.set_cloexec(true)?;
+        socket.set_nonblocking(true)?;
+        if socket.local_addr()?.is_unix() {
+            let listener = std::os::unix::net::UnixListener::from(OwnedFd::from(socket));
+            return Ok(Listener::Unix(UnixListener::from_std(listener)?, None));
+        }
+        Ok(Listener::Tcp(TcpListener::from_std(socket.into())?))
+    }
+
+    /// The port a TCP listener accepts on, wherever its address came from.
+    pub fn tcp_port(&self) -> Option<u16> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok().map(|addr| addr.port()),
+            Listener::Unix(..) => None,
+        }
+    }
+
+    async fn accept(&self) -> std::io::Result<Accepted> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().await.map(|(stream, peer)| Accepted::Tcp(stream, peer)),
+            Listener::Unix(listener, _) => listener.accept().await.map(|(stream, _)| Accepted::Unix(stream)),
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "tcp {}", addr),
+                Err(_) => write!(f, "tcp"),
+            },
+            Listener::Unix(listener, file) => {
+                let addr = listener.local_addr().ok();
+                match file.as_ref().map(|file| file.path.as_path()).or_else(|| addr.as_ref()?.as_pathname()) {
+                    Some(path) => write!(f, "unix {}", path.display()),
+                    None => write!(f, "unix"),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, Some(file)) = self {
+            if file.is_ours() {
+                let _ = std::fs::remove_file(&file.path);
+            }
+        }
+    }
+}
+
+/// Binds a Unix socket at `path` with `mode`, never reachable with other
+/// permissions: it's bound in a private directory next to `path`, given
+/// `mode` there, then moved into place.
+fn bind_with_mode(path: &Path, mode: u32) -> std::io::Result<UnixListener> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "socket path has no file name"))?;
+    let private = parent.join(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join(name);
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, Permissions::from_mode(mode))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    bound
+}
+
+/// Serves `router` on `listener`, over TLS if `tls` is set, until
+/// `shutdown` begins. Then stops accepting and gives in-flight requests
+/// until `drain_deadline` to finish before closing their connections.
+pub async fn serve(
+    listener: impl Into<Listener>,
+    tls: Option<Arc<CertStore>>,
+    router: Router,
+    shutdown: Shutdown,
+    drain_deadline: Duration,
+    http: HttpConfig,
+) {
+    let listener = listener.into();
+    let http = Arc::new(http);
+    let mut connections = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                match accepted {
+                    Ok(Accepted::Tcp(stream, peer)) => {
+                        let connection = serve_connection(stream, Some(peer), tls.clone(), router.clone(), shutdown.clone(), http.clone());
+                        connections.spawn(connection);
+                    }
+                    Ok(Accepted::Unix(stream)) => {
+                        connections.spawn(serve_connection(stream, None, None, router.clone(), shutdown.clone(), http.clone()));
+                    }
+                    Err(error) => warn!(%error, %listener, "Failed to accept connection"),
+                }
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.draining() => break,
+        }
+    }
+
+    info!(%listener, open_connections = connections.len(), "Stopped accepting connections; draining in-flight requests for up to {:?}", drain_deadline);
+    drop(listener);
+    let drained = tokio::time::timeout(drain_deadline, async {
+        while connections.join_next().await.is_some() {}
+    });
+    if drained.await.is_ok() {
+        info!("All connections drained");
+    } else {
+        warn!(open_connections = connections.len(), "Drain deadline of {:?} elapsed; closing remaining connections", drain_deadline);
+        connections.shutdown().await;
+    }
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    peer: Option<SocketAddr>,
+    tls: Option<Arc<CertStore>>,
+    router: Router,
+    shutdown: Shutdown,
+    http: Arc<HttpConfig>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let result = match tls {
+        None => drive(TokioIo::new(stream), http.protocol, peer, None, router, &shutdown, &http).await,
+        Some(store) => {
+            let stream = match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, store.acceptor().accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(error)) => return debug!(%error, ?peer, "TLS handshake failed"),
+                Err(_) => return debug!(?peer, "TLS handshake timed out"),
+            };
+            let session = stream.get_ref().1;
+            // The store only offers protocols `http.protocol` allows
+            let protocol = match session.alpn_protocol() {
+                Some(b"h2") => HttpProtocol::Http2,
+                _ if http.protocol == HttpProtocol::Http2 => HttpProtocol::Http2,
+                _ => HttpProtocol::Http1,
+            };
+            // Only verified certificates get this far
+            let certificate = session
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| store.client_principal(cert))
+                .map(|name| ClientCertificate { name });
+            drive(TokioIo::new(stream), protocol, peer, certificate, router, &shutdown, &http).await
+        }
+    };
+    if let Err(error) = result {
+        debug!(%error, ?peer, "Connection closed with an error");
+    }
+}
+
+/// Requests in flight on a connection, and when it last had none.
+struct Activity {
+    in_flight: AtomicUsize,
+    idle_since: Mutex<Instant>,
+}
+
+/// Counts a request as in flight until dropped.
+struct InFlight(Arc<Activity>);
+
+impl InFlight {
+    fn start(activity: &Arc<Activity>) -> Self {
+        activity.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(activity.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        *self.0.idle_since.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Activity {
+    /// Resolves once no request has been in flight for `timeout`.
+    async fn idle_for(&self, timeout: Duration) {
+        loop {
+            let deadline = *self.idle_since.lock().unwrap() + timeout;
+            let busy = self.in_flight.load(Ordering::SeqCst) > 0;
+            if !busy && Instant::now() >= deadline {
+                return;
+            }
+            // A request finishing moves the deadline, so check back later
+            tokio::time::sleep_until(if busy { Instant::now() + timeout } else { deadline }).await;
+        }
+    }
+}
+
+/// A connection builder for `protocol`, where `Auto` tells HTTP/1 and
+/// HTTP/2 apart by the connection preface.
+fn builder(protocol: HttpProtocol, http: &HttpConfig) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(Duration::from_secs(http.header_read_timeout_seconds))
+        .max_buf_size(http.max_header_bytes);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(http.max_concurrent_streams)
+        .max_header_list_size(u32::try_from(http.max_header_bytes).unwrap_or(u32::MAX));
+    match protocol {
+        HttpProtocol::Auto => builder,
+        HttpProtocol::Http1 => builder.http1_only(),
+        HttpProtocol::Http2 => builder.http2_only(),
+    }
+}
+
+/// Serves HTTP on one connection, closing it gracefully once shutdown
+/// begins or it has been idle for the keep-alive timeout.
+async fn drive<I>(
+    io: I,
+    protocol: HttpProtocol,
+    peer: Option<SocketAddr>,
+    certificate: Option<ClientCertificate>,
+    router: Router,
+    shutdown: &Shutdown,
+    http: &HttpConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    I: Read + Write + Unpin + Send + 'static,
+{
+    let activity = Arc::new(Activity { in_flight: AtomicUsize::new(0), idle_since: Mutex::new(Instant::now()) });
+    let service = {
+        let activity = activity.clone();
+        hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+            if let Some(peer) = peer {
+                request.extensions_mut().insert(ConnectInfo(peer));
+            }
+            if let Some(certificate) = &certificate {
+                request.extensions_mut().insert(certificate.clone());
+            }
+            let in_flight = InFlight::start(&activity);
+            let response = router.clone().oneshot(request);
+            async move {
+                let response = response.await;
+                drop(in_flight);
+                response
+            }
+        })
+    };
+    let builder = builder(protocol, http);
+    let connection = builder.serve_connection(io, service);
+    tokio::pin!(connection);
+    tokio::select! {
+        result = connection.as_mut() => return result,
+        _ = shutdown.draining() => {}
+        _ = activity.idle_for(Duration::from_secs(http.keep_alive_timeout_seconds)) => debug!(?peer, "Closing idle connection"),
+    }
+    connection.as_mut().graceful_shutdown();
+    connection.await
+}
@@ -10,23 +10,28 @@ impl UserService {
         UserService { repository }
     }
 
-    pub async fn get_all_users(&self) -> Vec<User> {
+    #[tracing::instrument(skip(self))]
+    pub async fn get_all_users(&self) -> Result<Vec<User>, String> {
         self.repository.get_all().await
     }
 
-    pub async fn get_user(&self, id: &str) -> Option<User> {
+    #[tracing::instrument(skip(self))]
+    pub async fn get_user(&self, id: &str) -> Result<Option<User>, String> {
         self.repository.get(id).await
     }
 
+    #[tracing::instrument(skip(self, user))]
     pub async fn create_user(&self, user: User) -> Result<User, String> {
         self.repository.create(user).await
     }
 
+    #[tracing::instrument(skip(self, user))]
     pub async fn update_user(&self, id: &str, user: User) -> Result<(), String> {
         self.repository.update(id, user).await
     }
 
-    pub async fn delete_user(&self, id: &str) -> bool {
+    #[tracing::instrument(skip(self))]
+    pub async fn delete_user(&self, id: &str) -> Result<bool, String> {
         self.repository.delete(id).await
     }
 }
\ No newline at end of file
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// The first descriptor systemd passes, after stdin, stdout and stderr.
+const LISTEN_FDS_START: RawFd = 3;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// The descriptors and `FileDescriptorName=`s of the sockets systemd passed
+/// to this process, going by `LISTEN_PID`, `LISTEN_FDS` and
+/// `LISTEN_FDNAMES` in `env`. Empty when they're unset or meant for
+/// another process.
+pub fn listen_fds(env: &HashMap<String, String>) -> io::Result<Vec<(RawFd, String)>> {
+    let Some(pid) = env.get("LISTEN_PID") else {
+        return Ok(Vec::new());
+    };
+    if pid.parse::<u32>().map_err(|_| invalid("invalid LISTEN_PID"))? != std::process::id() {
+        return Ok(Vec::new());
+    }
+    let count = match env.get("LISTEN_FDS") {
+        Some(count) => count.parse::<usize>().map_err(|_| invalid("invalid LISTEN_FDS"))?,
+        None => 0,
+    };
+    let names: Vec<String> = match env.get("LISTEN_FDNAMES") {
+        Some(names) => names.split(':').map(str::to_owned).collect(),
+        // What systemd calls sockets without a FileDescriptorName=
+        None => vec!["unknown".to_string(); count],
+    };
+    if names.len() != count {
+        return Err(invalid("LISTEN_FDNAMES doesn't name every LISTEN_FDS socket"));
+    }
+    Ok((LISTEN_FDS_START..).zip(names).collect())
+}
+
+/// Sockets passed in by systemd socket activation, taken by name as the
+/// listeners are set up.
+#[derive(Debug, Default)]
+pub struct ActivatedSockets(Vec<(String, OwnedFd)>);
+
+impl ActivatedSockets {
+    /// Takes ownership of the sockets [`listen_fds`] finds in `env`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else in the process may own or use the passed descriptors,
+    /// so this must be called at most once, with the process environment.
+    pub unsafe fn from_env(env: &HashMap<String, String>) -> io::Result<Self> {
+        let fds = listen_fds(env)?;
+        Ok(ActivatedSockets(fds.into_iter().map(|(fd, name)| (name, OwnedFd::from_raw_fd(fd))).collect()))
+    }
+
+    /// The names of the sockets not yet taken.
+    pub fn names(&self) -> Vec<String> {
+        self.0.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Takes the first remaining socket named `name`.
+    pub fn take(&mut self, name: &str) -> Option<OwnedFd> {
+        let index = self.0.iter().position(|(socket_name, _)| socket_name == name)?;
+        Some(self.0.remove(index).1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_name: &str) -> io::Result<SocketAddr> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets are Linux-only"))
+}
+
+/// Tells systemd about the service's state over `NOTIFY_SOCKET`, for units
+/// with `Type=notify`. Does nothing when that isn't set.
+pub struct Notifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog_timeout: Option<Duration>,
+}
+
+impl Notifier {
+    /// Reads `NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID` from `env`.
+    pub fn from_env(env: &HashMap<String, String>) -> io::Result<Self> {
+        let socket = match env.get("NOTIFY_SOCKET") {
+            Some(path) => {
+                let addr = match path.strip_prefix('@') {
+                    Some(name) => abstract_addr(name)?,
+                    None => SocketAddr::from_pathname(path)?,
+                };
+                Some((UnixDatagram::unbound()?, addr))
+            }
+            None => None,
+        };
+        let for_this_process = env
+            .get("WATCHDOG_PID")
+            .is_none_or(|pid| pid.parse::<u32>().ok() == Some(std::process::id()));
+        let watchdog_timeout = env
+            .get("WATCHDOG_USEC")
+            .filter(|_| for_this_process)
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0)
+            .map(Duration::from_micros);
+        Ok(Notifier { socket, watchdog_timeout })
+    }
+
+    /// A notifier that sends nothing.
+    pub fn disabled() -> Self {
+        Notifier { socket: None, watchdog_timeout: None }
+    }
+
+    fn notify(&self, state: &str) -> io::Result<()> {
+        if let Some((socket, addr)) = &self.socket {
+            socket.send_to_addr(state.as_bytes(), addr)?;
+        }
+        Ok(())
+    }
+
+    /// Startup is finished and the listeners are accepting connections.
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Shutdown has begun.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    /// How often [`watchdog`] pings: half the watchdog timeout, as systemd
+    /// recommends. `None` when the watchdog isn't enabled.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_timeout.filter(|_| self.socket.is_some()).map(|timeout| timeout / 2)
+    }
+}
+
+/// Pings the systemd watchdog every [`Notifier::watchdog_interval`] until
+/// dropped. Returns at once when the watchdog isn't enabled.
+pub async fn watchdog(notifier: Arc<Notifier>) {
+    let Some(period) = notifier.watchdog_interval() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(error) = notifier.notify("WATCHDOG=1") {
+            warn!(%error, "Failed to ping the systemd watchdog");
+        }
+    }
+}
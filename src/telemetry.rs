@@ -0,0 +1,88 @@
+use std::time::Duration;
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, Request, Response},
+};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::{field, Span};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::OtlpConfig;
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// Builds a tracer provider that batches spans to the configured OTLP/HTTP
+/// endpoint. Dropping the provider flushes any spans still queued.
+pub fn tracer_provider(config: &OtlpConfig) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+        .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+        .build())
+}
+
+/// A `tracing` layer that exports spans through `provider`.
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("hello_cargo"))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Creates the server span for a request, continuing the caller's trace when
+/// it sent W3C `traceparent`/`tracestate` headers.
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or("unmatched");
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        request_id,
+        otel.name = %format!("{} {}", request.method(), route),
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        http.route = route,
+        url.path = %request.uri().path(),
+        http.response.status_code = field::Empty,
+    );
+
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Fails only when no OpenTelemetry layer is installed, in which case
+    // there is no trace to continue.
+    let _ = span.set_parent(parent);
+    span
+}
+
+pub fn on_response<B>(response: &Response<B>, latency: Duration, span: &Span) {
+    span.record("http.response.status_code", response.status().as_u16());
+    tracing::debug!(status = %response.status(), latency = ?latency, "finished processing request");
+}
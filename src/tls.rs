@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use axum::{
+    extract::Request,
+    http::{header, uri::Authority, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json, Router,
+};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use serde_json::json;
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info};
+use x509_parser::extensions::GeneralName;
+
+use crate::config::{CertificateName, ClientAuthConfig, ClientAuthMode, HttpConfig, HttpProtocol, TlsConfig};
+
+/// ALPN protocols offered to clients for `protocol`, most preferred first.
+fn alpn_protocols(protocol: HttpProtocol) -> Vec<Vec<u8>> {
+    let protocols: &[&[u8]] = match protocol {
+        HttpProtocol::Auto => &[b"h2", b"http/1.1"],
+        HttpProtocol::Http1 => &[b"http/1.1"],
+        HttpProtocol::Http2 => &[b"h2"],
+    };
+    protocols.iter().map(|protocol| protocol.to_vec()).collect()
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|error| format!("cannot read {}: {}", path.display(), error))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| format!("cannot parse {}: {}", path.display(), error))?;
+    if certs.is_empty() {
+        return Err(format!("{} contains no certificates", path.display()));
+    }
+    Ok(certs)
+}
+
+fn load_crls(paths: &[PathBuf]) -> Result<Vec<CertificateRevocationListDer<'static>>, String> {
+    let mut crls = Vec::new();
+    for path in paths {
+        for crl in rustls_pemfile::crls(&mut open(path)?) {
+            crls.push(crl.map_err(|error| format!("cannot parse {}: {}", path.display(), error))?);
+        }
+    }
+    Ok(crls)
+}
+
+fn client_verifier(config: &ClientAuthConfig, provider: Arc<CryptoProvider>) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(&config.ca_path)? {
+        roots
+            .add(cert)
+            .map_err(|error| format!("invalid CA certificate in {}: {}", config.ca_path.display(), error))?;
+    }
+    // CAs without a CRL are trusted as is, so a bundle can mix CAs that
+    // publish CRLs with ones that don't.
+    let mut builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .with_crls(load_crls(&config.crl_paths)?)
+        .only_check_end_entity_revocation()
+        .allow_unknown_revocation_status();
+    if config.mode == ClientAuthMode::Optional {
+        builder = builder.allow_unauthenticated();
+    }
+    builder.build().map_err(|error| error.to_string())
+}
+
+/// Builds the rustls configuration: certificate, key, for mutual TLS the
+/// client CAs and CRLs, and the ALPN protocols `protocol` allows.
+pub(crate) fn server_config(config: &TlsConfig, protocol: HttpProtocol) -> Result<rustls::ServerConfig, String> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = load_certs(&config.cert_path)?;
+    let key = rustls_pemfile::private_key(&mut open(&config.key_path)?)
+        .map_err(|error| format!("cannot parse {}: {}", config.key_path.display(), error))?
+        .ok_or_else(|| format!("{} contains no private key", config.key_path.display()))?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|error| error.to_string())?;
+    let builder = match &config.client_auth {
+        Some(client_auth) => builder.with_client_cert_verifier(client_verifier(client_auth, provider)?),
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|error| format!("{} doesn't match {}: {}", config.key_path.display(), config.cert_path.display(), error))?;
+    server_config.alpn_protocols = alpn_protocols(protocol);
+    Ok(server_config)
+}
+
+/// The name `name` selects from a DER certificate, if it has one.
+pub(crate) fn certificate_name(cert: &CertificateDer, name: CertificateName) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    if name == CertificateName::CommonName {
+        return cert.subject().iter_common_name().next()?.as_str().ok().map(str::to_owned);
+    }
+    let sans = cert.subject_alternative_name().ok()??;
+    sans.value.general_names.iter().find_map(|general_name| match (name, general_name) {
+        (CertificateName::DnsSan, GeneralName::DNSName(value))
+        | (CertificateName::UriSan, GeneralName::URI(value))
+        | (CertificateName::EmailSan, GeneralName::RFC822Name(value)) => Some(value.to_string()),
+        _ => None,
+    })
+}
+
+/// The TLS configuration served to new connections, rebuilt when any of
+/// its files change on disk.
+#[derive(Debug)]
+pub struct CertStore {
+    config: TlsConfig,
+    protocol: HttpProtocol,
+    current: RwLock<Arc<rustls::ServerConfig>>,
+}
+
+impl CertStore {
+    /// Loads `config`, offering both HTTP/2 and HTTP/1.1.
+    pub fn load(config: &TlsConfig) -> Result<Self, String> {
+        Self::load_for(config, HttpProtocol::Auto)
+    }
+
+    /// Loads `config`, offering only the protocols `protocol` allows.
+    pub fn load_for(config: &TlsConfig, protocol: HttpProtocol) -> Result<Self, String> {
+        let server_config = server_config(config, protocol)?;
+        Ok(CertStore { config: config.clone(), protocol, current: RwLock::new(Arc::new(server_config)) })
+    }
+
+    /// Re-reads the certificate, key, client CAs and CRLs. On failure the
+    /// current configuration keeps being served.
+    pub fn reload(&self) -> Result<(), String> {
+        let server_config = server_config(&self.config, self.protocol)?;
+        *self.current.write().unwrap() = Arc::new(server_config);
+        Ok(())
+    }
+
+    /// An acceptor using the current configuration.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+
+    /// The principal name of a verified client certificate.
+    pub(crate) fn client_principal(&self, cert: &CertificateDer) -> Option<String> {
+        let client_auth = self.config.client_auth.as_ref()?;
+        certificate_name(cert, client_auth.principal_from)
+    }
+
+    fn snapshot(&self) -> Vec<Option<(SystemTime, u64)>> {
+        let client_auth = self.config.client_auth.iter();
+        [&self.config.cert_path, &self.config.key_path]
+            .into_iter()
+            .chain(client_auth.clone().map(|client_auth| &client_auth.ca_path))
+            .chain(client_auth.flat_map(|client_auth| &client_auth.crl_paths))
+            .map(|path| {
+                let metadata = std::fs::metadata(path).ok()?;
+                Some((metadata.modified().ok()?, metadata.len()))
+            })
+            .collect()
+    }
+}
+
+/// Reloads `store` whenever one of its files changes (checked every
+/// `poll`). Runs until dropped.
+pub async fn watch(store: Arc<CertStore>, poll: Duration) {
+    let mut files = store.snapshot();
+    let mut interval = tokio::time::interval(poll);
+    loop {
+        interval.tick().await;
+        let current = store.snapshot();
+        if current == files {
+            continue;
+        }
+        files = current;
+        match store.reload() {
+            Ok(()) => info!(cert_path = %store.config.cert_path.display(), "Reloaded TLS configuration"),
+            // Often the certificate was written before its key; the next
+            // change picks up the complete pair.
+            Err(error) => error!("Failed to reload TLS configuration, keeping the current one: {}", error),
+        }
+    }
+}
+
+/// Redirects every request to the same host and path over HTTPS on
+/// `https_port`.
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request| async move { redirect(request, https_port) })
+}
+
+/// The settings for the redirect listener: `http`, but speaking HTTP/1.1,
+/// since it's plain HTTP and `http2` would leave it answering only h2c,
+/// which browsers don't use.
+pub fn redirect_http_config(http: &HttpConfig) -> HttpConfig {
+    HttpConfig { protocol: HttpProtocol::Http1, ..http.clone() }
+}
+
+fn redirect(request: Request, https_port: u16) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .or_else(|| request.uri().authority().cloned());
+    let Some(host) = host else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "Missing Host header"}))).into_response();
+    };
+
+    let port = if https_port == 443 { String::new() } else { format!(":{}", https_port) };
+    let path = request.uri().path_and_query().map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("https://{}{}{}", host.host(), port, path)).into_response()
+}
@@ -0,0 +1,200 @@
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use axum::{
+    body::{Body, to_bytes},
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use hello_cargo::app;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hello_cargo::config::{AccessLogConfig, AccessLogFormat, ApiKeyConfig, AppConfig, LogSinkConfig, LogSinkKind, LogRotation};
+use serde_json::json;
+use tower::ServiceExt;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn access_logged_app(path: &Path, configure: impl FnOnce(&mut AccessLogConfig)) -> Router {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut config = AppConfig::default();
+    config.auth.api_keys.push(ApiKeyConfig {
+        name: "frontend".to_string(),
+        key: "user-key".to_string().into(),
+        admin: false,
+    });
+    config.log.access = AccessLogConfig {
+        enabled: true,
+        sinks: vec![LogSinkConfig {
+            kind: LogSinkKind::File,
+            format: None,
+            path: Some(path.to_path_buf()),
+            rotation: LogRotation::Never,
+            max_size_bytes: None,
+            max_files: 0,
+        }],
+        ..AccessLogConfig::default()
+    };
+    configure(&mut config.log.access);
+    app(user_repository, &config)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> StatusCode {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    to_bytes(response.into_body(), 4096).await.unwrap();
+    status
+}
+
+/// The lines written so far; dropping `app` flushes the background writer.
+fn lines(app: Router, path: &Path) -> Vec<String> {
+    drop(app);
+    std::fs::read_to_string(path).unwrap().lines().map(str::to_owned).collect()
+}
+
+#[tokio::test]
+async fn test_access_log_combined_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    let app = access_logged_app(&path, |_| {});
+    let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+
+    let request = Request::builder()
+        .uri("/users?token=abc123")
+        .header("x-api-key", "user-key")
+        .header("user-agent", "curl/8.0")
+        .extension(ConnectInfo(peer))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await, StatusCode::OK);
+
+    let lines = lines(app, &path);
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert!(line.starts_with("10.0.0.1 - frontend ["), "{}", line);
+    assert!(line.ends_with("\"GET /users?token=[REDACTED] HTTP/1.1\" 200 2 \"-\" \"curl/8.0\""), "{}", line);
+}
+
+#[tokio::test]
+async fn test_access_log_json_with_bodies() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    let app = access_logged_app(&path, |access| {
+        access.format = AccessLogFormat::Json;
+        access.capture_bodies = true;
+        access.redact_fields = vec!["name".to_string(), "user_agent".to_string()];
+    });
+
+    let user = json!({"id": "1", "name": "John Doe", "email": "john.doe@example.com"});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header("content-type", "application/json")
+        .header("user-agent", "curl/8.0")
+        .header("x-request-id", "access-1")
+        .body(Body::from(user.to_string()))
+        .unwrap();
+    assert_eq!(send(&app, request).await, StatusCode::CREATED);
+
+    let request = Request::builder().uri("/users/1").body(Body::empty()).unwrap();
+    assert_eq!(send(&app, request).await, StatusCode::OK);
+
+    let lines: Vec<serde_json::Value> = lines(app, &path).iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+
+    let created = &lines[0];
+    assert_eq!(created["request_id"], "access-1");
+    assert_eq!(created["method"], "POST");
+    assert_eq!(created["route"], "/users");
+    assert_eq!(created["status"], 201);
+    assert_eq!(created["bytes_in"], user.to_string().len());
+    assert_eq!(created["user_agent"], "[REDACTED]");
+    assert!(created["latency_ms"].is_f64());
+    assert!(created.get("principal").is_none());
+    let request_body: serde_json::Value = serde_json::from_str(created["request_body"].as_str().unwrap()).unwrap();
+    assert_eq!(request_body, json!({"id": "1", "name": "[REDACTED]", "email": "j***@example.com"}));
+
+    let fetched = &lines[1];
+    assert_eq!(fetched["route"], "/users/:id");
+    assert_eq!(fetched["path"], "/users/1");
+    assert_eq!(fetched["bytes_in"], 0);
+    assert!(fetched["bytes_out"].as_u64().unwrap() > 0);
+    assert!(fetched["response_body"].as_str().unwrap().contains("j***@example.com"));
+}
+
+#[tokio::test]
+async fn test_access_log_truncated_json_body_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    let app = access_logged_app(&path, |access| {
+        access.format = AccessLogFormat::Json;
+        access.capture_bodies = true;
+        access.max_body_bytes = 16;
+    });
+
+    let user = json!({"id": "1", "name": "John Doe", "email": "john.doe@example.com"});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header("content-type", "application/json")
+        .body(Body::from(user.to_string()))
+        .unwrap();
+    assert_eq!(send(&app, request).await, StatusCode::CREATED);
+
+    let line: serde_json::Value = serde_json::from_str(&lines(app, &path)[0]).unwrap();
+    assert_eq!(line["request_body"], "[TRUNCATED]");
+    assert_eq!(line["bytes_in"], user.to_string().len());
+}
+
+#[tokio::test]
+async fn test_access_log_counts_uncompressed_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    let app = access_logged_app(&path, |access| access.format = AccessLogFormat::Json);
+
+    // Large enough for the response to be compressed too
+    let user = json!({"id": "1", "name": "x".repeat(4096), "email": "john.doe@example.com"}).to_string();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(user.as_bytes()).unwrap();
+    let request = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header("content-type", "application/json")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::from(encoder.finish().unwrap()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    let compressed = to_bytes(response.into_body(), 1 << 16).await.unwrap();
+    let mut body = Vec::new();
+    GzDecoder::new(&compressed[..]).read_to_end(&mut body).unwrap();
+
+    let line: serde_json::Value = serde_json::from_str(&lines(app, &path)[0]).unwrap();
+    assert_eq!(line["bytes_in"], user.len());
+    assert_eq!(line["bytes_out"], body.len());
+}
+
+#[tokio::test]
+async fn test_access_log_trusts_its_own_proxies() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.log");
+    let app = access_logged_app(&path, |access| access.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()]);
+
+    for peer in ["10.0.0.1:4000", "192.0.2.1:4000"] {
+        let request = Request::builder()
+            .uri("/users")
+            .header("x-forwarded-for", "203.0.113.7")
+            .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&app, request).await, StatusCode::OK);
+    }
+
+    let lines = lines(app, &path);
+    assert!(lines[0].starts_with("203.0.113.7 - - ["), "{}", lines[0]);
+    // Not a proxy, so its header is ignored
+    assert!(lines[1].starts_with("192.0.2.1 - - ["), "{}", lines[1]);
+}
@@ -0,0 +1,120 @@
+//! Helpers shared by the integration tests. Each test binary uses only
+//! some of them.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use hello_cargo::config::{load_config_from, ConfigError, ConfigProblem};
+use hello_cargo::repositories::{PoolStats, RepositoryHealth, UserRepository};
+use hello_cargo::User;
+use tracing_subscriber::fmt::MakeWriter;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+/// Collects everything written to it, for inspecting log output.
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+
+    pub fn json_lines(&self) -> Vec<serde_json::Value> {
+        self.output()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("every line is JSON"))
+            .collect()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = CapturedLogs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Loads `toml` as the only config file, expecting it to be rejected, and
+/// returns the problems found.
+pub fn config_problems(toml: &str) -> Vec<ConfigProblem> {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("default.toml"), toml).unwrap();
+    match load_config_from(dir.path(), "development", Some(HashMap::new())) {
+        Err(ConfigError::Invalid(problems)) => problems,
+        other => panic!("expected invalid settings, got {:?}", other),
+    }
+}
+
+/// The keys of [`config_problems`], in the order they were reported.
+pub fn invalid_keys(toml: &str) -> Vec<String> {
+    config_problems(toml).into_iter().map(|problem| problem.key).collect()
+}
+
+/// A repository whose listing takes `delay`, whose lookup of the id
+/// `panic` panics and whose lookup or deletion of the id `error` fails.
+pub struct FaultyRepository {
+    inner: InMemoryUserRepository,
+    delay: Duration,
+    /// Reported as the statistics of a connection pool.
+    pub pool: Option<PoolStats>,
+}
+
+impl FaultyRepository {
+    pub fn new(delay: Duration) -> Self {
+        FaultyRepository { inner: InMemoryUserRepository::new(), delay, pool: None }
+    }
+}
+
+#[async_trait]
+impl UserRepository for FaultyRepository {
+    async fn get_all(&self) -> Result<Vec<User>, String> {
+        tokio::time::sleep(self.delay).await;
+        self.inner.get_all().await
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<User>, String> {
+        match id {
+            "panic" => panic!("lookup of {} failed", id),
+            "error" => Err(format!("lookup of {} failed", id)),
+            _ => self.inner.get(id).await,
+        }
+    }
+
+    async fn create(&self, user: User) -> Result<User, String> {
+        self.inner.create(user).await
+    }
+
+    async fn update(&self, id: &str, user: User) -> Result<(), String> {
+        self.inner.update(id, user).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, String> {
+        if id == "error" {
+            return Err(format!("deletion of {} failed", id));
+        }
+        self.inner.delete(id).await
+    }
+
+    async fn health(&self) -> RepositoryHealth {
+        self.inner.health().await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.pool.clone()
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::sync::Arc;
+use axum::{
+    body::{Body, to_bytes},
+    http::{header, Request, Response, StatusCode},
+    Router,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hello_cargo::app;
+use hello_cargo::config::{ApiKeyConfig, AppConfig, CompressionConfig};
+use hello_cargo::repositories::UserRepository;
+use hello_cargo::User;
+use serde_json::json;
+use tower::ServiceExt;
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+async fn compression_app(compression: CompressionConfig, users: usize) -> Router {
+    let user_repository = Arc::new(InMemoryUserRepository::new());
+    for index in 0..users {
+        let user = User::new(Some(format!("user-{}", index)), format!("User {}", index), format!("user{}@example.com", index));
+        user_repository.create(user).await.unwrap();
+    }
+    let mut config = AppConfig::default();
+    config.server.compression = compression;
+    // Lets requests reach /metrics
+    config.auth.api_keys.push(ApiKeyConfig { name: "admin".to_string(), key: ADMIN_KEY.to_string().into(), admin: true });
+    app(user_repository as Arc<dyn UserRepository>, &config)
+}
+
+const ADMIN_KEY: &str = "compression-admin-key";
+
+async fn get(app: &Router, uri: &str, accept_encoding: &str) -> Response<Body> {
+    let request = Request::builder()
+        .uri(uri)
+        .header(header::ACCEPT_ENCODING, accept_encoding)
+        .header("x-api-key", ADMIN_KEY)
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap()
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn decode(encoding: &str, bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    match encoding {
+        "gzip" => GzDecoder::new(bytes).read_to_end(&mut decoded).unwrap(),
+        "br" => brotli::Decompressor::new(bytes, 4096).read_to_end(&mut decoded).unwrap(),
+        "zstd" => zstd::stream::read::Decoder::new(bytes).unwrap().read_to_end(&mut decoded).unwrap(),
+        other => panic!("unexpected encoding {}", other),
+    };
+    decoded
+}
+
+async fn post_user(app: &Router, encoding: &str, body: Vec<u8>) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, encoding)
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1 << 20).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn test_responses_compressed_as_negotiated() {
+    let app = compression_app(CompressionConfig::default(), 500).await;
+
+    for encoding in ["gzip", "br", "zstd"] {
+        let response = get(&app, "/users", encoding).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], encoding);
+        assert!(response.headers()[header::VARY].to_str().unwrap().contains("accept-encoding"));
+        let body = to_bytes(response.into_body(), 1 << 20).await.unwrap();
+        let users: Vec<serde_json::Value> = serde_json::from_slice(&decode(encoding, &body)).unwrap();
+        assert_eq!(users.len(), 500);
+    }
+
+    let response = get(&app, "/users", "identity").await;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+}
+
+#[tokio::test]
+async fn test_small_and_unlisted_responses_not_compressed() {
+    let app = compression_app(CompressionConfig::default(), 1).await;
+    // A single user is well under min_size_bytes
+    let response = get(&app, "/users/user-0", "gzip").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+    let json_only = CompressionConfig { content_types: vec!["application/json".to_string()], min_size_bytes: 0, ..CompressionConfig::default() };
+    let app = compression_app(json_only, 1).await;
+    let response = get(&app, "/metrics", "gzip").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+    let disabled = CompressionConfig { enabled: false, min_size_bytes: 0, ..CompressionConfig::default() };
+    let app = compression_app(disabled, 500).await;
+    assert!(!get(&app, "/users", "gzip").await.headers().contains_key(header::CONTENT_ENCODING));
+}
+
+#[tokio::test]
+async fn test_compressed_request_bodies() {
+    let app = compression_app(CompressionConfig::default(), 0).await;
+    let user = json!({"id": "gzipped", "name": "Gzipped", "email": "gzipped@example.com"}).to_string();
+
+    let (status, created) = post_user(&app, "gzip", gzip(user.as_bytes())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["id"], "gzipped");
+
+    let user = json!({"id": "zstd", "name": "Zstd", "email": "zstd@example.com"}).to_string();
+    let (status, _) = post_user(&app, "zstd", zstd::encode_all(user.as_bytes(), 0).unwrap()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, error) = post_user(&app, "deflate", user.into_bytes()).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(error["error"], "Unsupported Content-Encoding");
+}
+
+#[tokio::test]
+async fn test_decompressed_size_limited() {
+    let app = compression_app(CompressionConfig::default(), 0).await;
+    // A few kilobytes on the wire, 8 MiB once inflated
+    let mut bomb = br#"{"id": "bomb", "name": "Bomb", "email": "bomb@example.com""#.to_vec();
+    bomb.extend(std::iter::repeat_n(b' ', 8 << 20));
+    bomb.push(b'}');
+    let compressed = gzip(&bomb);
+    assert!(compressed.len() < AppConfig::default().server.body_limit_bytes / 64);
+
+    let (status, _) = post_user(&app, "gzip", compressed).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_request_decompression_disabled() {
+    let app = compression_app(CompressionConfig { decompress_requests: false, ..CompressionConfig::default() }, 0).await;
+    let user = json!({"id": "gzipped", "name": "Gzipped", "email": "gzipped@example.com"}).to_string();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(gzip(user.as_bytes())))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(response.headers()[header::ACCEPT_ENCODING], "identity");
+}
+
+#[test]
+fn test_invalid_compression_config_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[server.compression]
+encodings = []
+content_types = ["json", "*/*"]
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    assert_eq!(
+        keys,
+        ["server.compression.encodings", "server.compression.content_types[0]", "server.compression.content_types[1]"]
+    );
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use std::sync::Arc;
+use axum::{
+    body::{Body, to_bytes},
+    http::{header, Request, Response, StatusCode},
+    Router,
+};
+use hello_cargo::build_app;
+use hello_cargo::config::{ApiKeyConfig, AppConfig, ConfigSources, CorsConfig};
+use hello_cargo::cors::OriginPattern;
+use hello_cargo::reload::ConfigReloader;
+use tower::ServiceExt;
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn cors_config() -> CorsConfig {
+    CorsConfig {
+        allowed_origins: vec![
+            "https://app.example.com".to_string(),
+            "https://*.staging.example.com".to_string(),
+            r"regex:https://pr-[0-9]+\.preview\.example\.com".to_string(),
+        ],
+        allow_credentials: true,
+        ..CorsConfig::default()
+    }
+}
+
+fn cors_app(cors: CorsConfig) -> (Router, Arc<ConfigReloader>) {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut config = AppConfig { cors, ..AppConfig::default() };
+    config.auth.api_keys = vec![ApiKeyConfig { name: "frontend".to_string(), key: "user-key".to_string().into(), admin: false }];
+    let app = build_app(user_repository, &config, ConfigSources::default());
+    (app.router, app.reloader)
+}
+
+async fn preflight(app: &Router, origin: &str, method: &str, headers: &str) -> Response<Body> {
+    let request = Request::builder()
+        .method("OPTIONS")
+        .uri("/users")
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap()
+}
+
+fn header(response: &Response<Body>, name: header::HeaderName) -> Option<&str> {
+    response.headers().get(name).map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn test_preflight_allowed() {
+    let (app, _) = cors_app(cors_config());
+
+    let response = preflight(&app, "https://app.example.com", "POST", "Content-Type, X-Api-Key").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_CREDENTIALS), Some("true"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_METHODS), Some("GET, POST, PUT, DELETE"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_HEADERS), Some("content-type, x-api-key, x-request-id"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_MAX_AGE), Some("600"));
+    assert!(header(&response, header::VARY).unwrap().contains("origin"));
+
+    for origin in ["https://a.staging.example.com", "https://a.b.staging.example.com", "https://pr-42.preview.example.com"] {
+        let response = preflight(&app, origin, "GET", "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT, "{}", origin);
+        assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some(origin));
+    }
+}
+
+#[tokio::test]
+async fn test_preflight_rejected() {
+    let (app, _) = cors_app(cors_config());
+
+    for (origin, method, headers) in [
+        ("https://evil.example.com", "GET", ""),
+        ("http://app.example.com", "GET", ""),
+        ("https://staging.example.com", "GET", ""),
+        ("https://a.staging.example.com.evil.com", "GET", ""),
+        ("https://pr-x.preview.example.com", "GET", ""),
+        ("https://app.example.com", "PATCH", ""),
+        ("https://app.example.com", "GET", "x-custom"),
+    ] {
+        let response = preflight(&app, origin, method, headers).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{} {} {}", origin, method, headers);
+        assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(error["error"].as_str().unwrap().starts_with("CORS preflight rejected"));
+    }
+}
+
+#[tokio::test]
+async fn test_cors_headers_on_responses() {
+    let (app, _) = cors_app(cors_config());
+
+    let request = Request::builder().uri("/users").header(header::ORIGIN, "https://app.example.com").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_EXPOSE_HEADERS), Some("x-request-id"));
+
+    // Errors from authentication are readable by the page too
+    let request = Request::builder()
+        .uri("/users")
+        .header(header::ORIGIN, "https://app.example.com")
+        .header("x-api-key", "wrong")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+
+    let request = Request::builder().uri("/users").header(header::ORIGIN, "https://evil.example.com").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+    assert_eq!(header(&response, header::VARY), Some("origin"));
+}
+
+#[tokio::test]
+async fn test_vary_on_requests_without_origin() {
+    let (app, _) = cors_app(cors_config());
+
+    // A cache must not hand this response to a page from an allowed origin
+    for api_key in ["user-key", "wrong"] {
+        let request = Request::builder().uri("/users").header("x-api-key", api_key).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+        assert_eq!(header(&response, header::VARY), Some("origin"));
+    }
+
+    // The same `*` answers every origin, so nothing varies
+    let (app, _) = cors_app(CorsConfig { allowed_origins: vec!["*".to_string()], ..CorsConfig::default() });
+    let response = app.clone().oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(header(&response, header::VARY), None);
+
+    let (app, _) = cors_app(CorsConfig::default());
+    let response = app.clone().oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(header(&response, header::VARY), None);
+}
+
+#[tokio::test]
+async fn test_any_origin_without_credentials() {
+    let (app, _) = cors_app(CorsConfig { allowed_origins: vec!["*".to_string()], allowed_headers: vec!["*".to_string()], ..CorsConfig::default() });
+
+    let response = preflight(&app, "https://anywhere.test", "PUT", "x-custom, content-type").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("*"));
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_CREDENTIALS), None);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_HEADERS), Some("x-custom, content-type"));
+}
+
+#[tokio::test]
+async fn test_cors_reloaded() {
+    let (app, reloader) = cors_app(CorsConfig::default());
+
+    // Disabled: the preflight reaches the router, which doesn't route OPTIONS
+    let response = preflight(&app, "https://app.example.com", "GET", "").await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+
+    let config = AppConfig { cors: cors_config(), ..reloader.current() };
+    let report = reloader.apply(config);
+    assert_eq!(report.applied, vec!["cors"]);
+    assert!(report.restart_required.is_empty());
+
+    let response = preflight(&app, "https://app.example.com", "GET", "").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(header(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+}
+
+#[test]
+fn test_origin_patterns() {
+    let pattern = OriginPattern::parse("https://*.example.com:8443").unwrap();
+    assert!(pattern.matches("https://api.example.com:8443"));
+    assert!(pattern.matches("HTTPS://API.EXAMPLE.COM:8443"));
+    assert!(!pattern.matches("https://api.example.com"));
+    assert!(!pattern.matches("https://.example.com:8443"));
+    assert!(!pattern.matches("https://a/b.example.com:8443"));
+
+    assert!(OriginPattern::parse("https://app.example.com/path").is_err());
+    assert!(OriginPattern::parse("app.example.com").is_err());
+    assert!(OriginPattern::parse("https://app.*.example.com").is_err());
+    assert!(OriginPattern::parse("regex:(").is_err());
+}
+
+#[test]
+fn test_invalid_cors_config_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+
+[cors]
+allowed_origins = ["*", "regex:["]
+allowed_methods = ["GET", "NOT A METHOD"]
+allowed_headers = ["content type"]
+allow_credentials = true
+"#,
+    );
+    assert_eq!(keys, ["cors.allowed_origins[0]", "cors.allowed_origins[1]", "cors.allowed_methods[1]", "cors.allowed_headers[0]"]);
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::body::Bytes;
+use axum::http::{Request, StatusCode, Version};
+use hello_cargo::build_app;
+use hello_cargo::config::{AppConfig, ConfigSources, HttpConfig, HttpProtocol};
+use hello_cargo::server::{self, Shutdown};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use common::{invalid_keys, FaultyRepository};
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+async fn start(http: HttpConfig) -> SocketAddr {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    start_with(user_repository, http).await
+}
+
+async fn start_with(user_repository: Arc<dyn hello_cargo::repositories::UserRepository>, http: HttpConfig) -> SocketAddr {
+    let app = build_app(user_repository, &AppConfig::default(), ConfigSources::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(listener, None, app.router, Shutdown::new(), Duration::from_secs(1), http));
+    addr
+}
+
+fn http_config(protocol: HttpProtocol) -> HttpConfig {
+    HttpConfig { protocol, ..HttpConfig::default() }
+}
+
+/// Opens an h2c connection with prior knowledge.
+async fn h2_connect(addr: SocketAddr) -> Result<(h2::client::SendRequest<Bytes>, tokio::task::JoinHandle<()>), h2::Error> {
+    let (client, connection) = h2::client::handshake(TcpStream::connect(addr).await.unwrap()).await?;
+    let connection = tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    Ok((client, connection))
+}
+
+/// Sends `GET path` over h2c, returning the status.
+async fn h2_get(addr: SocketAddr) -> Result<StatusCode, h2::Error> {
+    let (client, _connection) = h2_connect(addr).await?;
+    let mut client = client.ready().await?;
+    let request = Request::builder().uri(format!("http://{}/users", addr)).body(()).unwrap();
+    let (response, _) = client.send_request(request, true)?;
+    let response = response.await?;
+    assert_eq!(response.version(), Version::HTTP_2);
+    Ok(response.status())
+}
+
+/// Sends a raw HTTP/1.1 request and returns what comes back before the
+/// server closes the connection or goes quiet.
+async fn http1_exchange(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request).await.unwrap();
+    let mut response = vec![0; 4096];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response)).await.unwrap().unwrap_or(0);
+    String::from_utf8_lossy(&response[..read]).into_owned()
+}
+
+const GET_USERS: &[u8] = b"GET /users HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+#[tokio::test]
+async fn test_auto_serves_http1_and_h2c() {
+    let addr = start(http_config(HttpProtocol::Auto)).await;
+    assert_eq!(h2_get(addr).await.unwrap(), StatusCode::OK);
+    assert!(http1_exchange(addr, GET_USERS).await.starts_with("HTTP/1.1 200 OK"));
+}
+
+#[tokio::test]
+async fn test_http1_only_refuses_h2c() {
+    let addr = start(http_config(HttpProtocol::Http1)).await;
+    assert!(h2_get(addr).await.is_err());
+    assert!(http1_exchange(addr, GET_USERS).await.starts_with("HTTP/1.1 200 OK"));
+}
+
+#[tokio::test]
+async fn test_http2_only_refuses_http1() {
+    let addr = start(http_config(HttpProtocol::Http2)).await;
+    assert_eq!(h2_get(addr).await.unwrap(), StatusCode::OK);
+    assert!(!http1_exchange(addr, GET_USERS).await.starts_with("HTTP/1.1"));
+}
+
+#[tokio::test]
+async fn test_max_concurrent_streams_advertised() {
+    let addr = start(HttpConfig { max_concurrent_streams: 7, ..http_config(HttpProtocol::Http2) }).await;
+    let (client, _connection) = h2_connect(addr).await.unwrap();
+    let mut client = client.ready().await.unwrap();
+    let request = Request::builder().uri(format!("http://{}/users", addr)).body(()).unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    assert_eq!(client.current_max_send_streams(), 7);
+}
+
+#[tokio::test]
+async fn test_oversized_headers_rejected() {
+    let addr = start(HttpConfig { max_header_bytes: 8192, ..HttpConfig::default() }).await;
+    let request = format!("GET /users HTTP/1.1\r\nHost: localhost\r\nX-Padding: {}\r\n\r\n", "x".repeat(16 * 1024));
+    let response = http1_exchange(addr, request.as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+
+    let (client, _connection) = h2_connect(addr).await.unwrap();
+    let mut client = client.ready().await.unwrap();
+    let request = Request::builder()
+        .uri(format!("http://{}/users", addr))
+        .header("x-padding", "x".repeat(16 * 1024))
+        .body(())
+        .unwrap();
+    // Refused outright or answered with 431, depending on when h2 notices
+    if let Ok((response, _)) = client.send_request(request, true) {
+        if let Ok(response) = response.await {
+            assert_eq!(response.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_slow_headers_time_out() {
+    let addr = start(HttpConfig { header_read_timeout_seconds: 1, ..HttpConfig::default() }).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /users HTTP/1.1\r\nHost: local").await.unwrap();
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(3), stream.read_to_end(&mut response)).await.unwrap().unwrap();
+    assert!(!String::from_utf8_lossy(&response).contains("200 OK"));
+}
+
+#[tokio::test]
+async fn test_idle_connections_closed_after_keep_alive_timeout() {
+    let addr = start(HttpConfig { keep_alive_timeout_seconds: 1, ..HttpConfig::default() }).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /users HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = vec![0; 4096];
+    let read = stream.read(&mut response).await.unwrap();
+    assert!(String::from_utf8_lossy(&response[..read]).starts_with("HTTP/1.1 200 OK"));
+    // Kept alive, then closed once idle
+    let read = tokio::time::timeout(Duration::from_secs(3), stream.read(&mut response)).await.unwrap().unwrap();
+    assert_eq!(read, 0);
+
+    let (_client, connection) = h2_connect(addr).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(3), connection).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_h2_connection_kept_open_while_a_stream_outlasts_the_idle_timeout() {
+    let repository = Arc::new(FaultyRepository::new(Duration::from_secs(2)));
+    let addr = start_with(repository, HttpConfig { keep_alive_timeout_seconds: 1, ..HttpConfig::default() }).await;
+    let (client, connection) = h2_connect(addr).await.unwrap();
+
+    let send = |path: &str| {
+        let request = Request::builder().uri(format!("http://{}{}", addr, path)).body(()).unwrap();
+        let client = client.clone();
+        async move {
+            let mut client = client.ready().await?;
+            let (response, _) = client.send_request(request, true)?;
+            response.await.map(|response| response.status())
+        }
+    };
+    // The listing takes twice the idle timeout
+    assert_eq!(send("/users").await.unwrap(), StatusCode::OK);
+    // Still open for the next request, since it only just went idle
+    assert_eq!(send("/health/live").await.unwrap(), StatusCode::OK);
+
+    tokio::time::timeout(Duration::from_secs(3), connection).await.unwrap().unwrap();
+}
+
+#[test]
+fn test_invalid_http_settings_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[server.http]
+protocol = "http2"
+keep_alive_timeout_seconds = 0
+max_concurrent_streams = 0
+max_header_bytes = 1024
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    assert_eq!(
+        keys,
+        [
+            "server.http.keep_alive_timeout_seconds",
+            "server.http.max_concurrent_streams",
+            "server.http.max_header_bytes"
+        ]
+    );
+}
@@ -4,6 +4,7 @@ use axum::{
     http::{Request, StatusCode},
 };
 use hello_cargo::{app, User};
+use hello_cargo::config::AppConfig;
 use serde_json::json;
 use tower::ServiceExt;
 use ulid::Ulid;
@@ -13,7 +14,7 @@ use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
 #[tokio::test]
 async fn test_get_users() {
     let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
-    let app = app(user_repository);
+    let app = app(user_repository, &AppConfig::default());
 
     let response = app
         .oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap())
@@ -26,7 +27,7 @@ async fn test_get_users() {
 #[tokio::test]
 async fn test_create_user() {
     let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
-    let app = app(user_repository);
+    let app = app(user_repository, &AppConfig::default());
 
     let new_user = json!({
         "id": "",  // Empty string for auto-generated ULID
@@ -60,7 +61,7 @@ async fn test_create_user() {
 #[tokio::test]
 async fn test_get_user() {
     let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
-    let app = app(user_repository);
+    let app = app(user_repository, &AppConfig::default());
 
     // First, create a user
     let new_user = json!({
@@ -117,7 +118,7 @@ async fn test_get_user() {
 #[tokio::test]
 async fn test_create_duplicate_user() {
     let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
-    let app = app(user_repository);
+    let app = app(user_repository, &AppConfig::default());
 
     let user_id = Ulid::new().to_string();
     let new_user = json!({
@@ -153,17 +154,18 @@ async fn test_create_duplicate_user() {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap().to_owned();
 
     let body = to_bytes(response.into_body(), 1024).await.unwrap();
     let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
 
-    assert_eq!(error, json!({"error": "User ID already exists"}));
+    assert_eq!(error, json!({"error": "User ID already exists", "request_id": request_id}));
 }
 
 #[tokio::test]
 async fn test_update_user_id_conflict() {
     let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
-    let app = app(user_repository);
+    let app = app(user_repository, &AppConfig::default());
 
     // Create user 1
     let user1_id = Ulid::new().to_string();
@@ -222,9 +224,10 @@ async fn test_update_user_id_conflict() {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap().to_owned();
 
     let body = to_bytes(response.into_body(), 1024).await.unwrap();
     let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
 
-    assert_eq!(error, json!({"error": "New user ID already exists"}));
+    assert_eq!(error, json!({"error": "New user ID already exists", "request_id": request_id}));
 }
\ No newline at end of file
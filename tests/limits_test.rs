@@ -0,0 +1,212 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    body::{Body, to_bytes},
+    http::{header, Request, Response, StatusCode},
+    Router,
+};
+use hello_cargo::build_app;
+use hello_cargo::config::{AppConfig, ConfigSources, HttpConfig, RouteLimitConfig};
+use hello_cargo::repositories::UserRepository;
+use hello_cargo::server;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt;
+
+use common::{config_problems, FaultyRepository};
+
+fn route(path: &str, method: Option<&str>, body_limit_bytes: Option<usize>, request_timeout_seconds: Option<u64>) -> RouteLimitConfig {
+    RouteLimitConfig { path: path.to_string(), method: method.map(str::to_string), body_limit_bytes, request_timeout_seconds }
+}
+
+fn limits_app(config: &AppConfig, delay: Duration) -> Router {
+    let repository = Arc::new(FaultyRepository::new(delay)) as Arc<dyn UserRepository>;
+    build_app(repository, config, ConfigSources::default()).router
+}
+
+async fn serve(router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(listener, None, router, server::Shutdown::new(), Duration::from_secs(1), HttpConfig::default()));
+    addr
+}
+
+/// Writes `request`, then returns the raw response without closing the
+/// write side, so the request body may be left unfinished.
+async fn exchange(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request).await.unwrap();
+    let mut response = vec![0; 4096];
+    let read = stream.read(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response[..read]).into_owned()
+}
+
+async fn json(response: Response<Body>) -> serde_json::Value {
+    serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
+
+fn create_user(body: String) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// A valid user, padded with whitespace to `size` bytes.
+fn user_body(size: usize) -> String {
+    let user = serde_json::json!({"id": "", "name": "Padded", "email": "padded@example.com"}).to_string();
+    format!("{:<width$}", user, width = size)
+}
+
+#[tokio::test]
+async fn test_route_body_limit_overrides_global() {
+    let mut config = AppConfig::default();
+    config.server.body_limit_bytes = 1024;
+    config.server.routes = vec![route("/users", Some("post"), Some(4096), None)];
+    let app = limits_app(&config, Duration::ZERO);
+
+    let response = app.clone().oneshot(create_user(user_body(2048))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = app.clone().oneshot(create_user(user_body(8192))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let error = json(response).await;
+    assert_eq!(error["error"], "Request body too large");
+    assert!(error["request_id"].is_string());
+
+    // Other methods on the route keep the global limit
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/users/1")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(user_body(2048)))
+        .unwrap();
+    assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_chunked_body_over_limit_rejected() {
+    let mut config = AppConfig::default();
+    config.server.body_limit_bytes = 64;
+    let addr = serve(limits_app(&config, Duration::ZERO)).await;
+
+    let body = user_body(128);
+    let request = format!(
+        "POST /users HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+        body.len(),
+        body
+    );
+    let response = exchange(addr, request.as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    assert!(response.contains(r#""error":"Request body too large""#), "{}", response);
+}
+
+#[tokio::test]
+async fn test_slow_handler_times_out_with_504() {
+    let mut config = AppConfig::default();
+    config.server.routes = vec![route("/users", Some("GET"), None, Some(1))];
+    let app = limits_app(&config, Duration::from_secs(5));
+
+    let request = Request::builder().uri("/users").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    let error = json(response).await;
+    assert_eq!(error["error"], "Request timed out");
+    assert!(error["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn test_slow_request_body_times_out_with_408() {
+    let mut config = AppConfig::default();
+    config.server.request_timeout_seconds = 1;
+    let addr = serve(limits_app(&config, Duration::ZERO)).await;
+
+    let request = b"POST /users HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n{\"name\":";
+    let response = exchange(addr, request).await;
+    assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+    assert!(response.contains(r#""error":"Request body not received in time""#), "{}", response);
+}
+
+#[tokio::test]
+async fn test_requests_over_concurrency_limit_shed() {
+    let mut config = AppConfig::default();
+    config.server.max_concurrent_requests = 1;
+    let app = limits_app(&config, Duration::from_secs(5));
+
+    let in_flight = tokio::spawn(app.clone().oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = app.clone().oneshot(Request::builder().uri("/users/1").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    assert_eq!(json(response).await["error"], "Server is overloaded");
+
+    // Probes aren't shed, so an overloaded instance still reports live
+    let response = app.oneshot(Request::builder().uri("/health/live").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    in_flight.abort();
+}
+
+#[tokio::test]
+async fn test_handler_panic_answered_with_500() {
+    let app = limits_app(&AppConfig::default(), Duration::ZERO);
+
+    let response = app.clone().oneshot(Request::builder().uri("/users/panic").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let error = json(response).await;
+    assert_eq!(error["error"], "Internal server error");
+    assert!(error["request_id"].is_string());
+
+    let response = app.oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_invalid_limits_reported() {
+    let problems = config_problems(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+max_concurrent_requests = 0
+
+[[server.routes]]
+path = "users"
+method = "GET POST"
+body_limit_bytes = 0
+
+[[server.routes]]
+path = "/users/{id}"
+body_limit_bytes = 1024
+
+[[server.routes]]
+path = "/users/:id"
+method = "PUT"
+body_limit_bytes = 1024
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    let keys: Vec<&str> = problems.iter().map(|problem| problem.key.as_str()).collect();
+    for key in [
+        "server.max_concurrent_requests",
+        "server.routes[0].path",
+        "server.routes[0].method",
+        "server.routes[0].body_limit_bytes",
+        "server.routes[1].path",
+    ] {
+        assert!(keys.contains(&key), "missing {} in {:?}", key, keys);
+    }
+    assert!(!keys.iter().any(|key| key.starts_with("server.routes[2]")), "{:?}", keys);
+    let unknown = problems.iter().find(|problem| problem.key == "server.routes[1].path").unwrap();
+    assert!(unknown.message.contains("not a route"), "{}", unknown.message);
+}
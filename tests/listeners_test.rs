@@ -0,0 +1,176 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use hello_cargo::build_app;
+use hello_cargo::config::{AppConfig, ConfigSources, HttpConfig, ListenerConfig, ListenerRole};
+use hello_cargo::server::{self, Listener};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn unix_listener(path: PathBuf, serves: ListenerRole) -> ListenerConfig {
+    ListenerConfig { tcp: None, unix: Some(path), systemd: None, mode: Some("600".to_string()), serves, tls: false, trust_forwarded: false }
+}
+
+fn tcp_listener(serves: ListenerRole) -> ListenerConfig {
+    ListenerConfig { tcp: Some("127.0.0.1:0".parse().unwrap()), unix: None, systemd: None, mode: None, serves, tls: false, trust_forwarded: false }
+}
+
+/// Sends `GET path` and returns the status line.
+async fn get(stream: impl AsyncRead + AsyncWrite + Unpin, path: &str) -> String {
+    get_with(stream, path, "").await
+}
+
+/// Sends `GET path` with extra `headers` and returns the status line.
+async fn get_with(mut stream: impl AsyncRead + AsyncWrite + Unpin, path: &str, headers: &str) -> String {
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n", path, headers);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[tokio::test]
+async fn test_listeners_serve_their_routes_and_shut_down_together() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("api.sock");
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = build_app(user_repository, &AppConfig::default(), ConfigSources::default());
+
+    let (public_config, admin_config) = (unix_listener(socket.clone(), ListenerRole::Public), tcp_listener(ListenerRole::Admin));
+    let public = Listener::bind(&public_config).await.unwrap();
+    let admin = Listener::bind(&admin_config).await.unwrap();
+    let Listener::Tcp(tcp) = &admin else { unreachable!() };
+    let admin_addr = tcp.local_addr().unwrap();
+    assert_eq!(std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let (drain, http) = (Duration::from_secs(1), HttpConfig::default());
+    let servers = [
+        tokio::spawn(server::serve(public, None, app.router_for(&public_config), app.shutdown.clone(), drain, http.clone())),
+        tokio::spawn(server::serve(admin, None, app.router_for(&admin_config), app.shutdown.clone(), drain, http.clone())),
+    ];
+
+    assert_eq!(get(UnixStream::connect(&socket).await.unwrap(), "/users").await, "HTTP/1.1 200 OK");
+    assert_eq!(get(UnixStream::connect(&socket).await.unwrap(), "/health/live").await, "HTTP/1.1 404 Not Found");
+    assert_eq!(get(TcpStream::connect(admin_addr).await.unwrap(), "/health/live").await, "HTTP/1.1 200 OK");
+    assert_eq!(get(TcpStream::connect(admin_addr).await.unwrap(), "/users").await, "HTTP/1.1 404 Not Found");
+
+    app.shutdown.begin();
+    for server in servers {
+        tokio::time::timeout(Duration::from_secs(1), server).await.unwrap().unwrap();
+    }
+    assert!(!socket.exists());
+    assert!(TcpStream::connect(admin_addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_trusted_unix_peers_rate_limited_by_forwarded_address() {
+    let dir = tempfile::tempdir().unwrap();
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut config = AppConfig::default();
+    config.rate_limit.burst = 1;
+    config.rate_limit.per_second = 0.001;
+    let app = build_app(user_repository, &config, ConfigSources::default());
+
+    for trust_forwarded in [true, false] {
+        let socket = dir.path().join(format!("trusted-{}.sock", trust_forwarded));
+        let listener_config = ListenerConfig { trust_forwarded, ..unix_listener(socket.clone(), ListenerRole::Both) };
+        let listener = Listener::bind(&listener_config).await.unwrap();
+        let (drain, http) = (Duration::from_secs(1), HttpConfig::default());
+        tokio::spawn(server::serve(listener, None, app.router_for(&listener_config), app.shutdown.clone(), drain, http));
+
+        let from = |client: &str| format!("X-Forwarded-For: {}\r\n", client);
+        let first = get_with(UnixStream::connect(&socket).await.unwrap(), "/users", &from("203.0.113.1")).await;
+        assert_eq!(first, "HTTP/1.1 200 OK");
+        let second = get_with(UnixStream::connect(&socket).await.unwrap(), "/users", &from("203.0.113.2")).await;
+        if trust_forwarded {
+            // Each forwarded client has a bucket of its own
+            assert_eq!(second, "HTTP/1.1 200 OK");
+            let again = get_with(UnixStream::connect(&socket).await.unwrap(), "/users", &from("203.0.113.1")).await;
+            assert_eq!(again, "HTTP/1.1 429 Too Many Requests");
+        } else {
+            // Untrusted peers all share one bucket, whatever they claim
+            assert_eq!(second, "HTTP/1.1 429 Too Many Requests");
+        }
+    }
+    app.shutdown.begin();
+}
+
+#[tokio::test]
+async fn test_stale_unix_socket_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("api.sock");
+    let stale = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    drop(stale);
+    assert!(socket.exists());
+
+    let listener = Listener::bind(&unix_listener(socket.clone(), ListenerRole::Both)).await.unwrap();
+    assert_eq!(listener.to_string(), format!("unix {}", socket.display()));
+}
+
+#[tokio::test]
+async fn test_live_unix_socket_not_taken_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("api.sock");
+    let first = Listener::bind(&unix_listener(socket.clone(), ListenerRole::Both)).await.unwrap();
+    // Bound in a private directory that's gone once the socket is in place
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    let error = Listener::bind(&unix_listener(socket.clone(), ListenerRole::Both)).await.err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+    assert!(UnixStream::connect(&socket).await.is_ok());
+    drop(first);
+    assert!(!socket.exists());
+}
+
+#[tokio::test]
+async fn test_replaced_unix_socket_left_on_drop() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("api.sock");
+    let listener = Listener::bind(&unix_listener(socket.clone(), ListenerRole::Both)).await.unwrap();
+
+    // Another process removes the socket and binds its own in its place
+    std::fs::remove_file(&socket).unwrap();
+    let _other = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    drop(listener);
+    assert!(socket.exists());
+}
+
+#[test]
+fn test_invalid_listeners_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[[server.listeners]]
+tcp = "127.0.0.1:8080"
+unix = "/tmp/api.sock"
+
+[[server.listeners]]
+unix = "/tmp/api.sock"
+mode = "999"
+
+[[server.listeners]]
+tcp = "127.0.0.1:8443"
+tls = true
+trust_forwarded = true
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    assert_eq!(
+        keys,
+        ["server.listeners[0].unix", "server.listeners[1].mode", "server.listeners[2].trust_forwarded", "server.listeners[2].tls"]
+    );
+}
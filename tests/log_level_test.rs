@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    Router,
+};
+use hello_cargo::{app, build_app};
+use hello_cargo::config::{ApiKeyConfig, AppConfig, ConfigSources, LogConfig};
+use hello_cargo::logging;
+use serde_json::json;
+use tower::ServiceExt;
+use tracing::Level;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn admin_app() -> Router {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut config = AppConfig::default();
+    config.auth.api_keys = vec![
+        ApiKeyConfig { name: "ops".to_string(), key: "admin-key".to_string().into(), admin: true },
+        ApiKeyConfig { name: "frontend".to_string(), key: "user-key".to_string().into(), admin: false },
+    ];
+    app(user_repository, &config)
+}
+
+async fn send(app: &Router, method: &str, api_key: Option<&str>, body: Option<serde_json::Value>) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri("/admin/log-level")
+        .header("content-type", "application/json");
+    if let Some(api_key) = api_key {
+        request = request.header("x-api-key", api_key);
+    }
+    let body = body.map(|body| Body::from(body.to_string())).unwrap_or_default();
+
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 1024).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+// The filter is process-wide, so everything runs in a single test
+#[tokio::test]
+async fn test_runtime_log_level() {
+    let log_config = LogConfig { level: "INFO".to_string(), sinks: Vec::new(), ..LogConfig::default() };
+    let _logging = logging::setup_logging(&log_config).unwrap();
+    let app = admin_app();
+
+    let (status, _) = send(&app, "GET", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, "GET", Some("user-key"), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, state) = send(&app, "GET", Some("admin-key"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state["directives"], "hello_cargo=INFO,tower_http=debug,axum::rejection=trace");
+    assert!(!tracing::enabled!(target: "hello_cargo", Level::DEBUG));
+
+    let (status, error) = send(&app, "PUT", Some("admin-key"), Some(json!({"directives": "hello_cargo=loud"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error["error"].as_str().unwrap().starts_with("Invalid log directives"));
+
+    let (status, state) = send(&app, "PUT", Some("admin-key"), Some(json!({"directives": "hello_cargo=warn"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state["directives"], "hello_cargo=warn");
+    assert_eq!(state["default_directives"], "hello_cargo=warn");
+    assert!(!tracing::enabled!(target: "hello_cargo", Level::INFO));
+
+    // Rejected before the filter changes, so nothing is left to revert
+    for ttl_seconds in [u64::MAX, logging::MAX_TEMPORARY_TTL.as_secs() + 1] {
+        let oversized = json!({"directives": "hello_cargo=trace", "ttl_seconds": ttl_seconds});
+        let (status, error) = send(&app, "PUT", Some("admin-key"), Some(oversized)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(error["error"].as_str().unwrap().starts_with("ttl_seconds must be at most"), "{}", error);
+    }
+    let (_, state) = send(&app, "GET", Some("admin-key"), None).await;
+    assert_eq!(state["directives"], "hello_cargo=warn");
+    assert!(state.get("revert_in_seconds").is_none());
+    assert!(!tracing::enabled!(target: "hello_cargo", Level::TRACE));
+
+    let override_request = json!({"directives": "hello_cargo=debug", "ttl_seconds": 1});
+    let (status, state) = send(&app, "PUT", Some("admin-key"), Some(override_request)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state["directives"], "hello_cargo=debug");
+    assert_eq!(state["default_directives"], "hello_cargo=warn");
+    assert!(state["revert_in_seconds"].is_u64());
+    assert!(tracing::enabled!(target: "hello_cargo", Level::DEBUG));
+
+    // A config reload changes the default but leaves the override running
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut reloaded = AppConfig::default();
+    let reloader = build_app(user_repository, &reloaded, ConfigSources::default()).reloader;
+    reloaded.log.level = "ERROR".to_string();
+    assert!(reloader.apply(reloaded).applied.contains(&"log.level"));
+    let (_, state) = send(&app, "GET", Some("admin-key"), None).await;
+    assert_eq!(state["directives"], "hello_cargo=debug");
+    assert!(state["default_directives"].as_str().unwrap().starts_with("hello_cargo=ERROR,"));
+    assert!(tracing::enabled!(target: "hello_cargo", Level::DEBUG));
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let (_, state) = send(&app, "GET", Some("admin-key"), None).await;
+    assert!(state["directives"].as_str().unwrap().starts_with("hello_cargo=ERROR,"));
+    assert!(state.get("revert_in_seconds").is_none());
+    assert!(!tracing::enabled!(target: "hello_cargo", Level::WARN));
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::io::Write;
+use std::sync::Arc;
+use axum::{
+    body::{Body, to_bytes},
+    http::Request,
+};
+use hello_cargo::app;
+use hello_cargo::config::{AppConfig, LogFormat, LogRotation};
+use hello_cargo::logging::{fmt_layer, rotation::RotatingFile};
+use tower::ServiceExt;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+
+use common::CapturedLogs;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+#[test]
+fn test_json_flattens_span_fields() {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, logs.clone(), false));
+
+    tracing::subscriber::with_default(subscriber, || {
+        let request = tracing::info_span!("request", request_id = "01J0000000000000000000000");
+        let _request = request.enter();
+        let handler = tracing::info_span!("get_user", user_id = "42");
+        let _handler = handler.enter();
+        tracing::info!(found = true, "Looked up user");
+    });
+
+    let lines = logs.json_lines();
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert_eq!(line["message"], "Looked up user");
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["request_id"], "01J0000000000000000000000");
+    assert_eq!(line["user_id"], "42");
+    assert_eq!(line["found"], true);
+    assert_eq!(line["span"], "get_user");
+    assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+}
+
+#[tokio::test]
+async fn test_json_request_logs_carry_request_id() {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, logs.clone(), false));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/users")
+                .header("x-request-id", "trace-me")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    to_bytes(response.into_body(), 1024).await.unwrap();
+
+    let lines = logs.json_lines();
+    let finished = lines
+        .iter()
+        .find(|line| line["message"] == "finished processing request")
+        .expect("response is logged");
+    assert_eq!(finished["request_id"], "trace-me");
+    assert_eq!(finished["http.route"], "/users");
+    assert!(finished.get("otel.name").is_none());
+}
+
+#[test]
+fn test_file_sink_rotates_by_size_and_prunes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("logs").join("app.log");
+    let file = RotatingFile::new(path.clone(), LogRotation::Never, Some(100), 2).unwrap();
+
+    let line = [b'x'; 39];
+    for _ in 0..10 {
+        let mut writer = file.make_writer();
+        writer.write_all(&line).unwrap();
+        writer.write_all(b"\n").unwrap();
+    }
+
+    let mut rotated: Vec<String> = std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("app.log."))
+        .collect();
+    rotated.sort();
+
+    assert_eq!(rotated.len(), 2);
+    assert!(std::fs::metadata(&path).unwrap().len() <= 100);
+}
+
+#[test]
+fn test_prune_orders_same_second_rotations_numerically() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let stamp = "2020-01-01T00-00-00";
+    std::fs::write(dir.path().join(format!("app.log.{}", stamp)), "").unwrap();
+    for sequence in 1..=10 {
+        std::fs::write(dir.path().join(format!("app.log.{}.{}", stamp, sequence)), "").unwrap();
+    }
+    let file = RotatingFile::new(path, LogRotation::Never, Some(10), 3).unwrap();
+
+    for _ in 0..2 {
+        file.make_writer().write_all(b"0123456789\n").unwrap();
+    }
+
+    let mut kept: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(&format!("app.log.{}", stamp)))
+        .collect();
+    kept.sort();
+    assert_eq!(kept, [format!("app.log.{}.10", stamp), format!("app.log.{}.9", stamp)]);
+}
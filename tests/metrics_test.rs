@@ -0,0 +1,175 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    Router,
+};
+use hello_cargo::app;
+use hello_cargo::config::{ApiKeyConfig, AppConfig};
+use hello_cargo::repositories::{PoolStats, UserRepository};
+use serde_json::json;
+use tower::ServiceExt;
+
+use common::FaultyRepository;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+const ADMIN_KEY: &str = "metrics-admin-key";
+
+/// Default configuration plus an admin key to scrape with.
+fn config() -> AppConfig {
+    let mut config = AppConfig::default();
+    config.auth.admin_api_keys.push(ApiKeyConfig { name: "prometheus".to_string(), key: ADMIN_KEY.to_string().into(), admin: true });
+    config
+}
+
+async fn scrape(app: &Router) -> String {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/metrics").header("x-api-key", ADMIN_KEY).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics_use_route_templates() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &config());
+
+    for id in ["first", "second"] {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(&format!("/users/{}", id)).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    app.clone()
+        .oneshot(Request::builder().uri("/no/such/route").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let metrics = scrape(&app).await;
+
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="/users/:id",status="404"} 2"#));
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+    assert!(metrics.contains(r#"http_request_duration_seconds_count{method="GET",route="/users/:id",status="404"} 2"#));
+    assert!(!metrics.contains("/users/first"));
+    assert!(!metrics.contains("/no/such/route"));
+    // The scrape itself is in flight while metrics are gathered
+    assert!(metrics.contains("http_requests_in_flight 1"));
+}
+
+#[tokio::test]
+async fn test_metrics_repository_operations() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &config());
+
+    let new_user = json!({
+        "id": "duplicate",
+        "name": "John Doe",
+        "email": "john.doe@example.com"
+    });
+    for _ in 0..2 {
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/users")
+                    .header("content-type", "application/json")
+                    .body(Body::from(new_user.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    let metrics = scrape(&app).await;
+
+    assert!(metrics.contains(r#"repository_operation_duration_seconds_count{operation="create"} 2"#));
+    assert!(metrics.contains(r#"repository_errors_total{operation="create"} 1"#));
+    // The in-memory repository has no pool
+    assert!(!metrics.contains("db_pool_connections{"));
+}
+
+#[tokio::test]
+async fn test_metrics_require_admin_but_probes_stay_open() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &config());
+
+    let status = |uri: &'static str| {
+        let app = app.clone();
+        async move { app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap().status() }
+    };
+    assert_eq!(status("/metrics").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status("/health/live").await, StatusCode::OK);
+    assert_eq!(status("/health/ready").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_route_list_matches_the_routers() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &config());
+
+    for route in hello_cargo::ROUTES {
+        let uri = route.replace(":id", "someone");
+        app.clone()
+            .oneshot(Request::builder().uri(uri).header("x-api-key", ADMIN_KEY).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+    let metrics = scrape(&app).await;
+    for route in hello_cargo::ROUTES {
+        assert!(metrics.contains(&format!(r#"route="{}""#, route)), "{} isn't routed", route);
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_count_read_errors() {
+    let user_repository = Arc::new(FaultyRepository::new(Duration::ZERO)) as Arc<dyn UserRepository>;
+    let app = app(user_repository, &config());
+
+    for method in ["GET", "DELETE"] {
+        let request = Request::builder().method(method).uri("/users/error").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR, "{}", method);
+        // The cause stays in the log
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["error"], "Internal server error");
+    }
+    let request = Request::builder().uri("/users/missing").body(Body::empty()).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    let metrics = scrape(&app).await;
+    assert!(metrics.contains(r#"repository_errors_total{operation="get"} 1"#), "{}", metrics);
+    assert!(metrics.contains(r#"repository_errors_total{operation="delete"} 1"#), "{}", metrics);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_scrapes_count_pool_waits_once() {
+    let mut repository = FaultyRepository::new(Duration::ZERO);
+    repository.pool = Some(PoolStats { max_size: 4, connections: 2, idle_connections: 1, wait_count: 5, wait_seconds_total: 1.5 });
+    let app = app(Arc::new(repository) as Arc<dyn UserRepository>, &config());
+
+    let scrapes: Vec<_> = (0..16)
+        .map(|_| {
+            let app = app.clone();
+            tokio::spawn(async move { scrape(&app).await })
+        })
+        .collect();
+    for scrape in scrapes {
+        scrape.await.unwrap();
+    }
+    let metrics = scrape(&app).await;
+    assert!(metrics.contains("db_pool_wait_total 5\n"), "{}", metrics);
+    assert!(metrics.contains("db_pool_wait_seconds_total 1.5\n"), "{}", metrics);
+    assert!(metrics.contains(r#"db_pool_connections{state="active"} 1"#), "{}", metrics);
+}
@@ -0,0 +1,26 @@
+use hello_cargo::migrations::{embedded, MigrationReport};
+
+#[test]
+fn test_migrations_embedded_in_order() {
+    assert_eq!(embedded().unwrap(), vec!["00000000000000_diesel_initial_setup", "2024-09-08-054717_initial_users_repo"]);
+}
+
+#[test]
+fn test_schema_gating_lists_offending_migrations() {
+    let current = MigrationReport { applied: embedded().unwrap(), ..Default::default() };
+    assert!(current.check().is_ok());
+
+    let older = MigrationReport {
+        applied: vec!["00000000000000_diesel_initial_setup".to_string()],
+        pending: vec!["2024-09-08-054717_initial_users_repo".to_string()],
+        unknown: Vec::new(),
+    };
+    let message = older.check().unwrap_err().to_string();
+    assert!(message.contains("older"), "{}", message);
+    assert!(message.contains("2024-09-08-054717_initial_users_repo"), "{}", message);
+
+    let newer = MigrationReport { applied: embedded().unwrap(), pending: Vec::new(), unknown: vec!["20990101000000".to_string()] };
+    let message = newer.check().unwrap_err().to_string();
+    assert!(message.contains("newer"), "{}", message);
+    assert!(message.contains("20990101000000"), "{}", message);
+}
@@ -0,0 +1,183 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use hello_cargo::app;
+use hello_cargo::config::{AppConfig, CertificateName, ClientAuthConfig, ClientAuthMode, HttpConfig, TlsConfig};
+use hello_cargo::server::{self, Shutdown};
+use hello_cargo::tls::CertStore;
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateRevocationListParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyIdMethod, KeyPair, KeyUsagePurpose, RevokedCertParams, SanType, SerialNumber,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+const REVOKED_SERIAL: u64 = 3;
+
+struct Pki {
+    ca: rcgen::Certificate,
+    ca_key: KeyPair,
+    server: CertificateDer<'static>,
+}
+
+struct ClientCert {
+    chain: Vec<CertificateDer<'static>>,
+    key: Vec<u8>,
+}
+
+/// Writes a client CA, a CRL revoking serial [`REVOKED_SERIAL`] and a
+/// self-signed server certificate into `dir`.
+fn pki(dir: &Path) -> Pki {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign, KeyUsagePurpose::DigitalSignature];
+    params.distinguished_name.push(DnType::CommonName, "Test client CA");
+    let ca = params.self_signed(&ca_key).unwrap();
+    std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+    let crl = CertificateRevocationListParams {
+        this_update: rcgen::date_time_ymd(2020, 1, 1),
+        next_update: rcgen::date_time_ymd(2100, 1, 1),
+        crl_number: SerialNumber::from(1u64),
+        issuing_distribution_point: None,
+        revoked_certs: vec![RevokedCertParams {
+            serial_number: SerialNumber::from(REVOKED_SERIAL),
+            revocation_time: rcgen::date_time_ymd(2021, 1, 1),
+            reason_code: None,
+            invalidity_date: None,
+        }],
+        key_identifier_method: KeyIdMethod::Sha256,
+    }
+    .signed_by(&ca, &ca_key)
+    .unwrap();
+    std::fs::write(dir.join("crl.pem"), crl.pem().unwrap()).unwrap();
+
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("cert.pem"), cert.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), key_pair.serialize_pem()).unwrap();
+
+    Pki { ca, ca_key, server: cert.der().clone() }
+}
+
+fn client_cert(pki: &Pki, common_name: &str, serial: u64) -> ClientCert {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![format!("{}.internal", common_name)]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, common_name);
+    params
+        .subject_alt_names
+        .push(SanType::URI(format!("spiffe://example.org/{}", common_name).try_into().unwrap()));
+    params.serial_number = Some(SerialNumber::from(serial));
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let cert = params.signed_by(&key, &pki.ca, &pki.ca_key).unwrap();
+    ClientCert { chain: vec![cert.der().clone()], key: key.serialize_der() }
+}
+
+fn tls_config(dir: &Path, mode: ClientAuthMode, principal_from: CertificateName) -> TlsConfig {
+    TlsConfig {
+        cert_path: dir.join("cert.pem"),
+        key_path: dir.join("key.pem"),
+        redirect_port: None,
+        client_auth: Some(ClientAuthConfig {
+            ca_path: dir.join("ca.pem"),
+            mode,
+            crl_paths: vec![dir.join("crl.pem")],
+            principal_from,
+        }),
+    }
+}
+
+async fn start(tls: &TlsConfig, admin_certificates: &[&str]) -> SocketAddr {
+    let mut config = AppConfig::default();
+    config.auth.admin_certificates = admin_certificates.iter().map(|name| name.to_string()).collect();
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let router = app(user_repository, &config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let store = Arc::new(CertStore::load(tls).unwrap());
+    tokio::spawn(server::serve(listener, Some(store), router, Shutdown::new(), Duration::from_secs(1), HttpConfig::default()));
+    addr
+}
+
+/// Sends `GET path` over TLS, returning the status line, or `None` if the
+/// server refused the connection.
+async fn get(addr: SocketAddr, server_cert: &CertificateDer<'static>, client: Option<&ClientCert>, path: &str) -> Option<String> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(server_cert.clone()).unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match client {
+        Some(client) => {
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client.key.clone()));
+            builder.with_client_auth_cert(client.chain.clone(), key).unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .ok()?;
+    // With TLS 1.3 the server rejects the client certificate after the
+    // client considers the handshake done, so failures surface here.
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.ok()?;
+    response.lines().next().map(str::to_owned)
+}
+
+#[tokio::test]
+async fn test_required_client_certificate() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = pki(dir.path());
+    let addr = start(&tls_config(dir.path(), ClientAuthMode::Required, CertificateName::CommonName), &[]).await;
+
+    let client = client_cert(&pki, "billing", 2);
+    assert_eq!(get(addr, &pki.server, Some(&client), "/users").await.as_deref(), Some("HTTP/1.1 200 OK"));
+    assert_eq!(get(addr, &pki.server, None, "/users").await, None);
+}
+
+#[tokio::test]
+async fn test_revoked_client_certificate_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = pki(dir.path());
+    let addr = start(&tls_config(dir.path(), ClientAuthMode::Optional, CertificateName::CommonName), &[]).await;
+
+    let revoked = client_cert(&pki, "billing", REVOKED_SERIAL);
+    assert_eq!(get(addr, &pki.server, Some(&revoked), "/users").await, None);
+}
+
+#[tokio::test]
+async fn test_certificate_subject_becomes_principal() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = pki(dir.path());
+    let addr = start(&tls_config(dir.path(), ClientAuthMode::Optional, CertificateName::CommonName), &["billing"]).await;
+
+    let admin = client_cert(&pki, "billing", 2);
+    let other = client_cert(&pki, "reporting", 4);
+    // The admin endpoints answer 503 without a log controller, which is
+    // past authorization
+    assert_eq!(get(addr, &pki.server, Some(&admin), "/admin/log-level").await.as_deref(), Some("HTTP/1.1 503 Service Unavailable"));
+    assert_eq!(get(addr, &pki.server, Some(&other), "/admin/log-level").await.as_deref(), Some("HTTP/1.1 403 Forbidden"));
+    assert_eq!(get(addr, &pki.server, None, "/admin/log-level").await.as_deref(), Some("HTTP/1.1 401 Unauthorized"));
+}
+
+#[tokio::test]
+async fn test_principal_from_uri_san() {
+    let dir = tempfile::tempdir().unwrap();
+    let pki = pki(dir.path());
+    let tls = tls_config(dir.path(), ClientAuthMode::Required, CertificateName::UriSan);
+    let addr = start(&tls, &["spiffe://example.org/billing"]).await;
+
+    let admin = client_cert(&pki, "billing", 2);
+    assert_eq!(get(addr, &pki.server, Some(&admin), "/admin/log-level").await.as_deref(), Some("HTTP/1.1 503 Service Unavailable"));
+}
@@ -0,0 +1,146 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+    Router,
+};
+use hello_cargo::app;
+use hello_cargo::config::{ApiKeyConfig, AppConfig, RouteRateLimitConfig};
+use tower::ServiceExt;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn rate_limited_app(configure: impl FnOnce(&mut AppConfig)) -> Router {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let mut config = AppConfig::default();
+    config.rate_limit.per_second = 0.01;
+    config.rate_limit.burst = 2;
+    configure(&mut config);
+    app(user_repository, &config)
+}
+
+fn get_users_from(peer: &str) -> Request<Body> {
+    let peer: SocketAddr = peer.parse().unwrap();
+    Request::builder()
+        .uri("/users")
+        .extension(ConnectInfo(peer))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_rate_limit_exceeded() {
+    let app = rate_limited_app(|_| {});
+
+    for remaining in ["1", "0"] {
+        let response = app.clone().oneshot(get_users_from("10.0.0.1:4000")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-limit"], "2");
+        assert_eq!(response.headers()["ratelimit-remaining"], remaining);
+    }
+
+    let response = app.clone().oneshot(get_users_from("10.0.0.1:4000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["ratelimit-remaining"], "0");
+    assert!(response.headers().contains_key("ratelimit-reset"));
+    assert!(response.headers().contains_key("retry-after"));
+
+    // Other clients have their own bucket
+    let response = app.oneshot(get_users_from("10.0.0.2:4000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rate_limit_route_override() {
+    let app = rate_limited_app(|config| {
+        config.rate_limit.routes.push(RouteRateLimitConfig {
+            path: "/users/:id".to_string(),
+            method: None,
+            per_second: 0.01,
+            burst: 1,
+        });
+    });
+    let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+    let get_user = || {
+        Request::builder()
+            .uri("/users/missing")
+            .extension(ConnectInfo(peer))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(get_user()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["ratelimit-limit"], "1");
+
+    let response = app.clone().oneshot(get_user()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // The default bucket is untouched by the override
+    let response = app.oneshot(get_users_from("10.0.0.1:4000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["ratelimit-limit"], "2");
+}
+
+#[tokio::test]
+async fn test_rate_limit_trusted_proxy() {
+    let app = rate_limited_app(|config| {
+        config.rate_limit.trusted_proxies = vec!["192.168.0.0/16".parse().unwrap()];
+    });
+    let via_proxy = |forwarded_for: &str| {
+        let peer: SocketAddr = "192.168.1.1:4000".parse().unwrap();
+        Request::builder()
+            .uri("/users")
+            .header("x-forwarded-for", forwarded_for)
+            .extension(ConnectInfo(peer))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    for _ in 0..2 {
+        let response = app.clone().oneshot(via_proxy("203.0.113.7")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = app.clone().oneshot(via_proxy("203.0.113.7")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // A spoofed leftmost entry does not move the client into a fresh bucket
+    let response = app.clone().oneshot(via_proxy("198.51.100.1, 203.0.113.7")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let response = app.oneshot(via_proxy("203.0.113.8")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rate_limit_keyed_on_api_key() {
+    let app = rate_limited_app(|config| {
+        config.auth.api_keys.push(ApiKeyConfig {
+            name: "frontend".to_string(),
+            key: "secret-key".to_string().into(),
+            admin: false,
+        });
+    });
+    let with_key = |peer: &str| {
+        let peer: SocketAddr = peer.parse().unwrap();
+        Request::builder()
+            .uri("/users")
+            .header("x-api-key", "secret-key")
+            .extension(ConnectInfo(peer))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // The same key shares one bucket across client addresses
+    for peer in ["10.0.0.1:4000", "10.0.0.2:4000"] {
+        let response = app.clone().oneshot(with_key(peer)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = app.clone().oneshot(with_key("10.0.0.3:4000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let response = app.oneshot(get_users_from("10.0.0.3:4000")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use hello_cargo::config::{self, LogFormat};
+use hello_cargo::logging::fmt_layer;
+use hello_cargo::secret::Secret;
+use tracing_subscriber::layer::SubscriberExt;
+
+use common::CapturedLogs;
+
+fn capture(format: LogFormat, log: impl FnOnce()) -> String {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::registry().with(fmt_layer(format, logs.clone(), false));
+    tracing::subscriber::with_default(subscriber, log);
+    logs.output()
+}
+
+#[test]
+fn test_secret_is_redacted() {
+    let secret = Secret::new("hunter2".to_string());
+    assert_eq!(format!("{:?}", secret), "[REDACTED]");
+    assert_eq!(format!("{}", secret), "[REDACTED]");
+    assert_eq!(secret.expose(), "hunter2");
+}
+
+#[test]
+fn test_loaded_config_never_logs_database_password() {
+    let config = config::load_config().unwrap();
+
+    for format in [LogFormat::Full, LogFormat::Json] {
+        let output = capture(format, || tracing::debug!("Loaded configuration: {:?}", config));
+        assert!(!output.contains("NotSoStrongPassword"), "{}", output);
+        assert!(output.contains("[REDACTED]"), "{}", output);
+    }
+}
+
+#[test]
+fn test_sensitive_fields_are_masked() {
+    for format in [LogFormat::Full, LogFormat::Compact, LogFormat::Json] {
+        let output = capture(format, || {
+            tracing::info!(email = "john.doe@example.com", password = %"hunter2", user.api_key = ?"abc123", "Created user");
+        });
+        assert!(output.contains("j***@example.com"), "{}", output);
+        assert!(!output.contains("john.doe"), "{}", output);
+        assert!(!output.contains("hunter2"), "{}", output);
+        assert!(!output.contains("abc123"), "{}", output);
+    }
+}
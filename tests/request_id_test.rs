@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use hello_cargo::app;
+use hello_cargo::config::AppConfig;
+use tower::ServiceExt;
+use ulid::Ulid;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+#[tokio::test]
+async fn test_request_id_generated() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let response = app
+        .oneshot(Request::builder().uri("/users").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(Ulid::from_string(request_id).is_ok());
+}
+
+#[tokio::test]
+async fn test_request_id_propagated_into_errors() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/users/missing")
+                .header("x-request-id", "client-supplied-id")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["x-request-id"], "client-supplied-id");
+
+    let body = to_bytes(response.into_body(), 1024).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(error["error"], "User not found");
+    assert_eq!(error["request_id"], "client-supplied-id");
+}
+
+#[tokio::test]
+async fn test_invalid_request_id_replaced() {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/users")
+                .header("x-request-id", "contains spaces and \"quotes\"")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(Ulid::from_string(request_id).is_ok());
+}
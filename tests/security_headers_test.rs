@@ -0,0 +1,144 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+    Router,
+};
+use hello_cargo::build_app;
+use hello_cargo::auth::API_KEY_HEADER;
+use hello_cargo::config::{load_config_from, ApiKeyConfig, AppConfig, ConfigSources, DocsMode, FrameOptions};
+use tower::ServiceExt;
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn headers_app(config: &AppConfig) -> Router {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    build_app(user_repository, config, ConfigSources::default()).router
+}
+
+async fn get(app: &Router, uri: &str, api_key: Option<&str>) -> Response<Body> {
+    let mut request = Request::builder().uri(uri);
+    if let Some(api_key) = api_key {
+        request = request.header(API_KEY_HEADER, api_key);
+    }
+    app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+fn docs_config(mode: DocsMode) -> AppConfig {
+    let mut config = AppConfig::default();
+    config.docs.mode = mode;
+    config.auth.api_keys = vec![ApiKeyConfig { name: "reader".to_string(), key: "reader-key".to_string().into(), admin: false }];
+    config
+}
+
+#[tokio::test]
+async fn test_security_headers_on_api_responses() {
+    let app = headers_app(&AppConfig::default());
+
+    for uri in ["/users", "/users/missing", "/health/live"] {
+        let response = get(&app, uri, None).await;
+        let headers = response.headers();
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=31536000", "{}", uri);
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff", "{}", uri);
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY", "{}", uri);
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer", "{}", uri);
+        assert_eq!(headers[header::CONTENT_SECURITY_POLICY], "default-src 'none'; frame-ancestors 'none'", "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_swagger_ui_gets_its_own_content_security_policy() {
+    let app = headers_app(&AppConfig::default());
+
+    let response = get(&app, "/swagger-ui/", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let policy = response.headers()[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+    assert!(policy.contains("default-src 'self'"), "{}", policy);
+    assert!(policy.contains("style-src 'self' 'unsafe-inline'"), "{}", policy);
+    assert_eq!(response.headers()[header::X_FRAME_OPTIONS], "DENY");
+}
+
+#[tokio::test]
+async fn test_security_headers_configurable() {
+    let mut config = AppConfig::default();
+    config.server.security_headers.hsts_max_age_seconds = 600;
+    config.server.security_headers.hsts_include_subdomains = true;
+    config.server.security_headers.frame_options = FrameOptions::SameOrigin;
+    config.server.security_headers.referrer_policy = "same-origin".to_string();
+    config.server.security_headers.content_security_policy = String::new();
+    let app = headers_app(&config);
+
+    let response = get(&app, "/users", None).await;
+    let headers = response.headers();
+    assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=600; includeSubDomains");
+    assert_eq!(headers[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+    assert_eq!(headers[header::REFERRER_POLICY], "same-origin");
+    assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+
+    config.server.security_headers.enabled = false;
+    let response = get(&headers_app(&config), "/users", None).await;
+    for name in [header::STRICT_TRANSPORT_SECURITY, header::X_CONTENT_TYPE_OPTIONS, header::X_FRAME_OPTIONS] {
+        assert!(!response.headers().contains_key(&name), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn test_docs_modes() {
+    let app = headers_app(&docs_config(DocsMode::Public));
+    assert_eq!(get(&app, "/swagger-ui/", None).await.status(), StatusCode::OK);
+    assert_eq!(get(&app, "/api-docs/openapi.json", None).await.status(), StatusCode::OK);
+
+    let app = headers_app(&docs_config(DocsMode::Authenticated));
+    for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+        assert_eq!(get(&app, uri, None).await.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        assert_eq!(get(&app, uri, Some("reader-key")).await.status(), StatusCode::OK, "{}", uri);
+    }
+    // The API itself stays open to anonymous callers
+    assert_eq!(get(&app, "/users", None).await.status(), StatusCode::OK);
+
+    let app = headers_app(&docs_config(DocsMode::Disabled));
+    for uri in ["/swagger-ui/", "/api-docs/openapi.json"] {
+        assert_eq!(get(&app, uri, Some("reader-key")).await.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+    assert_eq!(get(&app, "/users", None).await.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_production_disables_docs() {
+    let config_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+    let env = HashMap::from([("APP_DATABASE_URL".to_string(), "postgres://localhost/hello_cargo".to_string())]);
+    let config = load_config_from(&config_dir, "production", Some(env.clone())).unwrap();
+    assert_eq!(config.docs.mode, DocsMode::Disabled);
+
+    let config = load_config_from(&config_dir, "development", Some(env)).unwrap();
+    assert_eq!(config.docs.mode, DocsMode::Public);
+}
+
+#[test]
+fn test_invalid_security_headers_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[server.security_headers]
+referrer_policy = "sometimes"
+content_security_policy = "default-src 'none'\u0001"
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    assert_eq!(
+        keys,
+        ["server.security_headers.referrer_policy", "server.security_headers.content_security_policy"]
+    );
+}
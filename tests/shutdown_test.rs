@@ -0,0 +1,110 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use hello_cargo::build_app;
+use hello_cargo::config::{AppConfig, ConfigSources, HttpConfig};
+use hello_cargo::repositories::UserRepository;
+use hello_cargo::server::{self, Shutdown, ShutdownSignals};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+use common::FaultyRepository;
+
+struct Running {
+    addr: SocketAddr,
+    app: axum::Router,
+    shutdown: Shutdown,
+    server: JoinHandle<()>,
+}
+
+async fn start(delay: Duration, drain_deadline: Duration) -> Running {
+    let repository = Arc::new(FaultyRepository::new(delay)) as Arc<dyn UserRepository>;
+    let app = build_app(repository, &AppConfig::default(), ConfigSources::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(server::serve(listener, None, app.router.clone(), app.shutdown.clone(), drain_deadline, HttpConfig::default()));
+    Running { addr, app: app.router, shutdown: app.shutdown, server }
+}
+
+/// Sends `GET /users` and returns the raw response, empty if the
+/// connection was closed without one.
+fn get_users(addr: SocketAddr) -> JoinHandle<String> {
+    tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /users HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        String::from_utf8_lossy(&response).into_owned()
+    })
+}
+
+async fn readiness(app: &axum::Router) -> StatusCode {
+    let request = Request::builder().uri("/health/ready").body(Body::empty()).unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_in_flight_request_completes_during_shutdown() {
+    let running = start(Duration::from_millis(500), Duration::from_secs(5)).await;
+    assert_eq!(readiness(&running.app).await, StatusCode::OK);
+
+    let in_flight = get_users(running.addr);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    running.shutdown.begin();
+    assert_eq!(readiness(&running.app).await, StatusCode::SERVICE_UNAVAILABLE);
+
+    let response = in_flight.await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("[]"), "{}", response);
+
+    tokio::time::timeout(Duration::from_secs(1), running.server).await.unwrap().unwrap();
+    assert!(TcpStream::connect(running.addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_drain_deadline_closes_slow_connections() {
+    let running = start(Duration::from_secs(30), Duration::from_millis(200)).await;
+
+    let in_flight = get_users(running.addr);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    running.shutdown.begin();
+
+    tokio::time::timeout(Duration::from_secs(2), running.server).await.unwrap().unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(1), in_flight).await.unwrap().unwrap();
+    assert_eq!(response, "");
+}
+
+/// Sends `signal` to this test process.
+fn kill(signal: &str) {
+    let status = std::process::Command::new("kill").args([&format!("-{}", signal), &std::process::id().to_string()]).status();
+    assert!(status.unwrap().success());
+}
+
+#[tokio::test]
+async fn test_signals_drain_the_server() {
+    let mut signals = ShutdownSignals::new().unwrap();
+    let running = start(Duration::from_millis(500), Duration::from_secs(5)).await;
+
+    let in_flight = get_users(running.addr);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    kill("TERM");
+    let signal = tokio::time::timeout(Duration::from_secs(2), signals.recv()).await.unwrap();
+    assert_eq!(signal, "SIGTERM");
+    running.shutdown.begin();
+
+    let response = in_flight.await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    tokio::time::timeout(Duration::from_secs(1), running.server).await.unwrap().unwrap();
+
+    kill("INT");
+    let signal = tokio::time::timeout(Duration::from_secs(2), signals.recv()).await.unwrap();
+    assert_eq!(signal, "SIGINT");
+}
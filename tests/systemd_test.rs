@@ -0,0 +1,193 @@
+mod common;
+
+use std::collections::HashMap;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixDatagram;
+use std::sync::Arc;
+use std::time::Duration;
+use hello_cargo::build_app;
+use hello_cargo::config::{AppConfig, ConfigSources, HttpConfig, ServerConfig};
+use hello_cargo::server::{self, Listener, Shutdown};
+use hello_cargo::systemd::{self, listen_fds, Notifier};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+    vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// Binds a datagram socket to stand in for systemd's `NOTIFY_SOCKET`.
+fn notify_socket(dir: &tempfile::TempDir) -> (UnixDatagram, String) {
+    let path = dir.path().join("notify.sock");
+    let socket = UnixDatagram::bind(&path).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+    (socket, path.display().to_string())
+}
+
+fn received(socket: &UnixDatagram) -> String {
+    let mut buffer = [0; 256];
+    let read = socket.recv(&mut buffer).unwrap();
+    String::from_utf8_lossy(&buffer[..read]).into_owned()
+}
+
+#[test]
+fn test_notifier_sends_ready_and_stopping() {
+    let dir = tempfile::tempdir().unwrap();
+    let (socket, path) = notify_socket(&dir);
+    let notifier = Notifier::from_env(&env(&[("NOTIFY_SOCKET", &path)])).unwrap();
+
+    notifier.ready().unwrap();
+    assert_eq!(received(&socket), "READY=1");
+    notifier.stopping().unwrap();
+    assert_eq!(received(&socket), "STOPPING=1");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_notifier_reaches_abstract_socket() {
+    use std::os::linux::net::SocketAddrExt;
+    let name = format!("hello-cargo-test-{}", std::process::id());
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(&name).unwrap();
+    let socket = UnixDatagram::bind_addr(&addr).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+
+    let notifier = Notifier::from_env(&env(&[("NOTIFY_SOCKET", &format!("@{}", name))])).unwrap();
+    notifier.ready().unwrap();
+    assert_eq!(received(&socket), "READY=1");
+}
+
+#[test]
+fn test_notifier_without_socket_does_nothing() {
+    let notifier = Notifier::from_env(&env(&[("WATCHDOG_USEC", "200000")])).unwrap();
+    notifier.ready().unwrap();
+    notifier.stopping().unwrap();
+    assert_eq!(notifier.watchdog_interval(), None);
+}
+
+#[tokio::test]
+async fn test_watchdog_pings_at_half_the_timeout() {
+    let dir = tempfile::tempdir().unwrap();
+    let (socket, path) = notify_socket(&dir);
+    let pid = std::process::id().to_string();
+    let notifier =
+        Notifier::from_env(&env(&[("NOTIFY_SOCKET", &path), ("WATCHDOG_USEC", "200000"), ("WATCHDOG_PID", &pid)])).unwrap();
+    assert_eq!(notifier.watchdog_interval(), Some(Duration::from_millis(100)));
+
+    let watchdog = tokio::spawn(systemd::watchdog(Arc::new(notifier)));
+    let socket = tokio::task::spawn_blocking(move || {
+        assert_eq!(received(&socket), "WATCHDOG=1");
+        assert_eq!(received(&socket), "WATCHDOG=1");
+    });
+    socket.await.unwrap();
+    watchdog.abort();
+
+    // A watchdog meant for another process isn't ours to ping
+    let notifier = Notifier::from_env(&env(&[("NOTIFY_SOCKET", &path), ("WATCHDOG_USEC", "200000"), ("WATCHDOG_PID", "1")]))
+        .unwrap();
+    assert_eq!(notifier.watchdog_interval(), None);
+}
+
+#[test]
+fn test_listen_fds_read_from_env() {
+    let pid = std::process::id().to_string();
+    let fds = listen_fds(&env(&[("LISTEN_PID", &pid), ("LISTEN_FDS", "2"), ("LISTEN_FDNAMES", "web:admin")])).unwrap();
+    assert_eq!(fds, [(3, "web".to_string()), (4, "admin".to_string())]);
+
+    let fds = listen_fds(&env(&[("LISTEN_PID", &pid), ("LISTEN_FDS", "1")])).unwrap();
+    assert_eq!(fds, [(3, "unknown".to_string())]);
+
+    // Inherited from a parent that was socket-activated
+    assert!(listen_fds(&env(&[("LISTEN_PID", "1"), ("LISTEN_FDS", "2")])).unwrap().is_empty());
+    assert!(listen_fds(&env(&[])).unwrap().is_empty());
+
+    assert!(listen_fds(&env(&[("LISTEN_PID", &pid), ("LISTEN_FDS", "2"), ("LISTEN_FDNAMES", "web")])).is_err());
+    assert!(listen_fds(&env(&[("LISTEN_PID", &pid), ("LISTEN_FDS", "many")])).is_err());
+}
+
+#[test]
+fn test_activated_sockets_replace_host_and_port() {
+    let server = ServerConfig::default();
+    let listeners = server.effective_listeners(&["web".to_string(), "admin".to_string()]);
+    let names: Vec<_> = listeners.iter().map(|listener| listener.systemd.as_deref()).collect();
+    assert_eq!(names, [Some("web"), Some("admin")]);
+    assert!(listeners.iter().all(|listener| listener.tcp.is_none()));
+
+    let listeners = server.effective_listeners(&[]);
+    assert_eq!(listeners[0].tcp, Some(std::net::SocketAddr::new(server.host, server.port)));
+}
+
+#[tokio::test]
+async fn test_listener_from_passed_tcp_socket() {
+    let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let listener = Listener::from_fd(OwnedFd::from(socket)).unwrap();
+    assert_eq!(listener.to_string(), format!("tcp {}", addr));
+    // What the HTTPS redirect points at, with no address in the config
+    assert_eq!(listener.tcp_port(), Some(addr.port()));
+
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = build_app(user_repository, &AppConfig::default(), ConfigSources::default());
+    tokio::spawn(server::serve(listener, None, app.router, Shutdown::new(), Duration::from_secs(1), HttpConfig::default()));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /health/live HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_listener_from_passed_unix_socket_keeps_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("api.sock");
+    let socket = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let listener = Listener::from_fd(OwnedFd::from(socket)).unwrap();
+    assert_eq!(listener.to_string(), format!("unix {}", path.display()));
+    assert_eq!(listener.tcp_port(), None);
+    // systemd owns the socket file and keeps it for the next instance
+    drop(listener);
+    assert!(path.exists());
+}
+
+#[tokio::test]
+async fn test_listener_from_fd_rejects_other_sockets() {
+    let (socket, _) = UnixDatagram::pair().unwrap();
+    assert!(Listener::from_fd(OwnedFd::from(socket)).is_err());
+
+    let connected = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = std::net::TcpStream::connect(connected.local_addr().unwrap()).unwrap();
+    assert!(Listener::from_fd(OwnedFd::from(stream)).is_err());
+}
+
+#[test]
+fn test_conflicting_systemd_listener_reported() {
+    let keys = invalid_keys(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[[server.listeners]]
+systemd = "web"
+
+[[server.listeners]]
+systemd = "admin"
+tcp = "127.0.0.1:8081"
+
+[[server.listeners]]
+systemd = "metrics"
+mode = "660"
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+    );
+    assert_eq!(keys, ["server.listeners[1].systemd", "server.listeners[2].mode"]);
+}
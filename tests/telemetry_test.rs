@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use hello_cargo::app;
+use hello_cargo::config::{AppConfig, OtlpConfig};
+use hello_cargo::telemetry;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::trace::v1::Span;
+use prost::Message;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Starts an OTLP/HTTP collector stub and returns its traces endpoint along
+/// with a channel receiving every exported span.
+async fn start_collector() -> (String, mpsc::UnboundedReceiver<Span>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let collector = Router::new().route(
+        "/v1/traces",
+        post(move |body: Bytes| async move {
+            let request = ExportTraceServiceRequest::decode(body).unwrap();
+            for resource_spans in request.resource_spans {
+                for scope_spans in resource_spans.scope_spans {
+                    for span in scope_spans.spans {
+                        tx.send(span).unwrap();
+                    }
+                }
+            }
+            StatusCode::OK
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, collector).await.unwrap() });
+
+    (format!("http://{}/v1/traces", addr), rx)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_spans_exported_with_propagated_context() {
+    let (endpoint, mut exported) = start_collector().await;
+    let config = OtlpConfig {
+        enabled: true,
+        endpoint,
+        service_name: "hello_cargo_test".to_string(),
+        // Nothing would be sampled locally; the caller's sampled flag must win
+        sample_ratio: 0.0,
+    };
+    let provider = telemetry::tracer_provider(&config).unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let new_user = json!({
+        "id": "",
+        "name": "John Doe",
+        "email": "john.doe@example.com"
+    });
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .header("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID))
+                .header("tracestate", "vendor=value")
+                .body(Body::from(new_user.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    // The request span stays open until the response body has been sent
+    to_bytes(response.into_body(), 1024).await.unwrap();
+
+    drop(guard);
+    tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+
+    let mut spans = Vec::new();
+    while let Ok(span) = exported.try_recv() {
+        spans.push(span);
+    }
+
+    let request_span = spans.iter().find(|span| span.name == "POST /users").expect("request span exported");
+    assert_eq!(hex(&request_span.trace_id), TRACE_ID);
+    assert_eq!(hex(&request_span.parent_span_id), PARENT_SPAN_ID);
+    assert_eq!(request_span.trace_state, "vendor=value");
+
+    // Handler, service and repository spans all join the caller's trace
+    let names: Vec<&str> = spans.iter().map(|span| span.name.as_str()).collect();
+    assert_eq!(names.iter().filter(|name| **name == "create_user").count(), 2);
+    assert!(names.contains(&"create"));
+    assert!(spans.iter().all(|span| hex(&span.trace_id) == TRACE_ID));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unsampled_parent_is_not_exported() {
+    let (endpoint, mut exported) = start_collector().await;
+    let config = OtlpConfig { enabled: true, endpoint, ..OtlpConfig::default() };
+    let provider = telemetry::tracer_provider(&config).unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let app = app(user_repository, &AppConfig::default());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/users")
+                .header("traceparent", format!("00-{}-{}-00", TRACE_ID, PARENT_SPAN_ID))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), 1024).await.unwrap();
+
+    drop(guard);
+    tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+
+    assert!(exported.try_recv().is_err());
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode, Version},
+};
+use hello_cargo::app;
+use hello_cargo::config::{AppConfig, HttpConfig, HttpProtocol, TlsConfig};
+use hello_cargo::server::{self, Shutdown};
+use hello_cargo::tls::{self, CertStore};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::pki_types::{CertificateDer, ServerName};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tower::ServiceExt;
+
+use common::invalid_keys;
+use hello_cargo::repositories::in_memory_repository::InMemoryUserRepository;
+
+/// Writes a fresh self-signed certificate for `localhost` and returns it.
+fn self_signed(dir: &Path) -> (TlsConfig, CertificateDer<'static>) {
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let config = TlsConfig { cert_path: dir.join("cert.pem"), key_path: dir.join("key.pem"), redirect_port: None, client_auth: None };
+    std::fs::write(&config.cert_path, cert.pem()).unwrap();
+    std::fs::write(&config.key_path, key_pair.serialize_pem()).unwrap();
+    (config, cert.der().clone())
+}
+
+async fn start(store: Arc<CertStore>) -> SocketAddr {
+    let user_repository = Arc::new(InMemoryUserRepository::new()) as Arc<dyn hello_cargo::repositories::UserRepository>;
+    let router = app(user_repository, &AppConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve(listener, Some(store), router, Shutdown::new(), Duration::from_secs(1), HttpConfig::default()));
+    addr
+}
+
+async fn connect(addr: SocketAddr, trusted: &CertificateDer<'static>, alpn: &[&[u8]]) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(trusted.clone()).unwrap();
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+    let stream = TcpStream::connect(addr).await?;
+    TlsConnector::from(Arc::new(config)).connect(ServerName::try_from("localhost").unwrap(), stream).await
+}
+
+#[tokio::test]
+async fn test_https_negotiates_h2_via_alpn() {
+    let dir = tempfile::tempdir().unwrap();
+    let (config, cert) = self_signed(dir.path());
+    let addr = start(Arc::new(CertStore::load(&config).unwrap())).await;
+
+    let stream = connect(addr, &cert, &[b"h2", b"http/1.1"]).await.unwrap();
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+    let (mut sender, connection) = hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await.unwrap();
+    tokio::spawn(connection);
+    let request = Request::builder().uri("https://localhost/health/live").body(Body::empty()).unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), Version::HTTP_2);
+}
+
+#[tokio::test]
+async fn test_https_serves_http1_without_alpn() {
+    let dir = tempfile::tempdir().unwrap();
+    let (config, cert) = self_signed(dir.path());
+    let addr = start(Arc::new(CertStore::load(&config).unwrap())).await;
+
+    let mut stream = connect(addr, &cert, &[]).await.unwrap();
+    stream.write_all(b"GET /health/live HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_rotated_certificate_served_without_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let (config, old_cert) = self_signed(dir.path());
+    let store = Arc::new(CertStore::load(&config).unwrap());
+    let addr = start(store.clone()).await;
+    tokio::spawn(tls::watch(store, Duration::from_millis(50)));
+    assert!(connect(addr, &old_cert, &[]).await.is_ok());
+
+    let (_, new_cert) = self_signed(dir.path());
+    let mut rotated = false;
+    for _ in 0..40 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if connect(addr, &new_cert, &[]).await.is_ok() {
+            rotated = true;
+            break;
+        }
+    }
+    assert!(rotated, "new certificate was never served");
+    assert!(connect(addr, &old_cert, &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_reload_keeps_the_protocols_offered() {
+    let dir = tempfile::tempdir().unwrap();
+    let (config, _) = self_signed(dir.path());
+    let store = Arc::new(CertStore::load_for(&config, HttpProtocol::Http1).unwrap());
+    let addr = start(store.clone()).await;
+
+    let (_, cert) = self_signed(dir.path());
+    store.reload().unwrap();
+    let stream = connect(addr, &cert, &[b"h2", b"http/1.1"]).await.unwrap();
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+}
+
+#[tokio::test]
+async fn test_invalid_certificate_rejected_on_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut config, _) = self_signed(dir.path());
+    config.key_path = dir.path().join("missing.pem");
+    let error = CertStore::load(&config).unwrap_err();
+    assert!(error.contains("missing.pem"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_http_redirected_to_https() {
+    let redirect = tls::redirect_router(8443);
+
+    let request = Request::builder().uri("/users?page=2").header(header::HOST, "example.com:8080").body(Body::empty()).unwrap();
+    let response = redirect.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[header::LOCATION], "https://example.com:8443/users?page=2");
+
+    let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let response = redirect.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_redirect_speaks_http1_when_https_is_http2_only() {
+    let http = HttpConfig { protocol: HttpProtocol::Http2, ..HttpConfig::default() };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let redirect = tls::redirect_router(8443);
+    tokio::spawn(server::serve(listener, None, redirect, Shutdown::new(), Duration::from_secs(1), tls::redirect_http_config(&http)));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /users HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 308 Permanent Redirect"), "{}", response);
+    assert!(response.to_ascii_lowercase().contains("location: https://example.com:8443/users"), "{}", response);
+}
+
+/// Problems with a TLS config served on `listener`, e.g. `tcp = "..."`.
+fn tls_listener_problems(tls_extra: &str, listener: &str) -> Vec<String> {
+    let dir = tempfile::tempdir().unwrap();
+    let (config, _) = self_signed(dir.path());
+    invalid_keys(&format!(
+        r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[server.tls]
+cert_path = "{}"
+key_path = "{}"
+{}
+
+[[server.listeners]]
+{}
+
+[log]
+level = "INFO"
+
+[database]
+url = "postgres://localhost/hello_cargo"
+"#,
+        config.cert_path.display(),
+        config.key_path.display(),
+        tls_extra,
+        listener
+    ))
+}
+
+#[test]
+fn test_tls_needs_a_tls_listener() {
+    assert_eq!(tls_listener_problems("", "tcp = \"127.0.0.1:8443\""), ["server.tls"]);
+}
+
+#[test]
+fn test_redirect_needs_a_tls_listener() {
+    let keys = tls_listener_problems("redirect_port = 8081", "tcp = \"127.0.0.1:8443\"");
+    assert_eq!(keys, ["server.tls", "server.tls.redirect_port"]);
+}